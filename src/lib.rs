@@ -0,0 +1,15 @@
+extern crate cgmath;
+extern crate crossbeam;
+extern crate exr;
+extern crate image;
+extern crate num_cpus;
+extern crate rand;
+#[macro_use]
+extern crate serde;
+extern crate tobj;
+extern crate toml;
+
+pub mod raytracing;
+
+pub use raytracing::{Integrator, Renderer, RenderSettings, RenderStats};
+pub use raytracing::{TileOrder, TileScheduler};
@@ -2,175 +2,334 @@
 // :TODO:
 // - Multiple shapes - better/more efficient storage/organisation.
 // - Shading model.
-// - Lights.
-// - Efficient scene organisation.
-// - Multi-threaded rendering.
-// - Spectral path tracing (single scalar per ray? Randomly pick a wavelength per bounce?)
-// - Preview output in window.
-// - Command line args parsing (output to file/window).
 // */
 
-extern crate cgmath;
-extern crate crossbeam;
-extern crate image;
-extern crate itertools;
-extern crate num_cpus;
-extern crate rand;
+extern crate clap;
+extern crate rs_pathtracer;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
-mod raytracing;
-
-use cgmath::*;
-use image::ColorType;
-use image::png::PNGEncoder;
-use rand::{random};
-use raytracing::cameras::{Camera};
-use raytracing::materials::{Dialectric, DiffuseLight, Lambertian, Metal};
-use raytracing::{BoxedHitable, HitableCollection, Ray};
-use raytracing::shapes::{Cuboid, Plane, RectXY, RectXZ, RectYZ, Sphere};
-use raytracing::util::{random};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use clap::builder::RangedU64ValueParser;
+use rs_pathtracer::{Integrator, Renderer, RenderSettings, RenderStats, TileOrder, TileScheduler};
+use rs_pathtracer::raytracing::Film;
+use rs_pathtracer::raytracing::aov::{Aov, AovBuffer};
+use rs_pathtracer::raytracing::denoiser::Denoiser;
+use rs_pathtracer::raytracing::checkpoint::{self, CheckpointHeader};
+use rs_pathtracer::raytracing::output::{self, ExrLayer, OutputFormat, ToneMapOperator, ToneMapping};
+use rs_pathtracer::raytracing::samplers::SamplerType;
+use rs_pathtracer::raytracing::scenes;
+use rs_pathtracer::raytracing::tracing::PathLimits;
+#[cfg(feature = "sdl")]
 use sdl2::pixels::PixelFormatEnum;
+#[cfg(feature = "sdl")]
 use sdl2::rect::Rect;
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
-use std::time::{Duration, Instant};
-use std::fs::File;
-
-fn write_png_rgb8(filename: &str, pixels: &[u8], dimensions: (u32, u32))
-    -> Result<(), std::io::Error>
-{
-    let output = File::create(filename)?;
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(pixels, dimensions.0, dimensions.1, ColorType::RGB(8))?;
-    Ok(())
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+#[cfg(feature = "sdl")]
+use std::thread;
+#[cfg(feature = "sdl")]
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(about = "Toy path tracer", args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Image width in pixels
+    #[arg(long, default_value_t = 640, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    width: usize,
+    /// Image height in pixels
+    #[arg(long, default_value_t = 400, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    height: usize,
+    /// Samples per pixel taken in each iteration
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    samples: u32,
+    /// Number of iterations to accumulate before writing the output
+    #[arg(short = 'i', long, default_value_t = 2000, value_parser = clap::value_parser!(u32).range(1..))]
+    max_iterations: u32,
+    /// Scene to render, either a scene file or a built-in scene (cornell, test)
+    #[arg(long, default_value = "cornell")]
+    scene: String,
+    #[command(flatten)]
+    image: ImageArgs,
+    /// Number of render threads (defaults to the number of CPUs)
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+    /// Size in pixels of the square tiles the image is split into between the threads
+    #[arg(long, default_value_t = 32, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    tile_size: usize,
+    /// Order the tiles are handed out in: hilbert, spiral (from the centre) or scanline
+    #[arg(long, default_value = "hilbert")]
+    tile_order: TileOrder,
+    /// Integrator: mis (multiple importance sampling), light-sampling (next-event estimation) or brute-force
+    #[arg(long, default_value = "mis")]
+    integrator: Integrator,
+    /// Sampler: sobol (Owen-scrambled), halton, stratified (within each iteration's samples) or independent
+    #[arg(long, default_value = "sobol")]
+    sampler: SamplerType,
+    /// Seed for the random numbers, renders with the same seed and settings match exactly
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Stop sampling pixels once the standard error of their mean is below this fraction of it (e.g. 0.01)
+    #[arg(long)]
+    adaptive_threshold: Option<f32>,
+    /// Samples every pixel takes before adaptive sampling can stop it
+    #[arg(long, default_value_t = 32)]
+    adaptive_min_samples: u32,
+    /// Also write the number of samples taken by each pixel to this image (normalised to the largest count in a PNG)
+    #[arg(long)]
+    sample_map: Option<String>,
+    /// AOVs to write as layers of an EXR output, or else as files next to it (e.g. output.albedo.png), separated by commas: albedo, emission, normal, camera-normal, depth, position, object-index, material-index
+    #[arg(long, value_delimiter = ',')]
+    aovs: Vec<Aov>,
+    /// Most diffuse (and glossy) bounces a path can take
+    #[arg(long, default_value_t = 50)]
+    max_diffuse_depth: u32,
+    /// Most mirror reflections a path can take
+    #[arg(long, default_value_t = 50)]
+    max_specular_depth: u32,
+    /// Most refractions through glass a path can take
+    #[arg(long, default_value_t = 50)]
+    max_transmission_depth: u32,
+    /// Bounces before Russian roulette can end paths that carry little light
+    #[arg(long, default_value_t = 3)]
+    roulette_depth: u32,
+    /// Trace a few wavelengths along each path rather than red, green and blue, turning the scene's RGB colours into spectra
+    #[arg(long)]
+    spectral: bool,
+    /// Render without opening a preview window
+    #[arg(long)]
+    no_window: bool,
+    /// Iterations between intermediate writes of the output when rendering without a window (0 to disable)
+    #[arg(long, default_value_t = 50)]
+    write_interval: u32,
+    /// Save the render's raw progress to this file periodically and on exit, to carry on with --resume
+    #[arg(long)]
+    checkpoint: Option<String>,
+    /// Iterations between saves of the checkpoint (0 to only save on exit)
+    #[arg(long, default_value_t = 50)]
+    checkpoint_interval: u32,
+    /// Carry on from the checkpoint, which must be of the same scene and files at the same resolution, rendered with the same seed and sampling options
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
 }
 
-fn scene_test() -> HitableCollection {
-    let mut shapes: HitableCollection = Vec::new();
-    shapes.push(Box::new(Sphere { origin: Point3::new(0., 0., 0.), radius: 0.5, material: Box::new(Lambertian { albedo: vec3(0.1, 0.2, 0.5) }) }));
-    shapes.push(Box::new(Plane { origin: Point3::new(0., -0.5, 0.), normal: vec3(0., 1., 0.), material: Box::new(Lambertian { albedo: vec3(0.2, 0.5, 0.2) }) }));
-    shapes.push(Box::new(Sphere { origin: Point3::new(1., 0., 0.), radius: 0.5, material: Box::new(Metal { albedo: vec3(0.8, 0.6, 0.2), fuzziness: 0.3 }) }));
-    shapes.push(Box::new(Sphere { origin: Point3::new(-1., 0., 0.), radius: 0.5, material: Box::new(Dialectric { refractive_index: 1.5 }) }));
-    //shapes.push(Box::new(Sphere { origin: Point3::new(-1., 0., 0.), radius: -0.45, material: Box::new(Dialectric { refractive_index: 1.5 }) }));
-    shapes.push(Box::new(RectXZ { x0: -0.5, x1: 0.5, z0: -0.5, z1: 0.5, k: 2., material: Box::new(DiffuseLight { colour: vec3(4., 4., 4.) }) }));
-    shapes
+#[derive(Subcommand)]
+enum Command {
+    /// Combine checkpoints of the same render made separately (e.g. on several machines with different seeds) into one image
+    Merge(MergeArgs),
 }
 
-fn scene_cornell_box() -> HitableCollection {
-    let mut shapes: HitableCollection = Vec::new();
-    // Walls
-    shapes.push(Box::new(RectYZ { y0: 0., y1: 555., z0: 0., z1: 555., k: 555., material: Box::new(Lambertian { albedo: vec3(0.12, 0.45, 0.15) }) }));
-    shapes.push(Box::new(RectYZ { y0: 0., y1: 555., z0: 0., z1: 555., k: 0., material: Box::new(Lambertian { albedo: vec3(0.65, 0.05, 0.05) }) }));
-    shapes.push(Box::new(RectXZ { x0: 0., x1: 555., z0: 0., z1: 555., k: 0., material: Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73) }) }));
-    shapes.push(Box::new(RectXZ { x0: 0., x1: 555., z0: 0., z1: 555., k: 555., material: Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73) }) }));
-    shapes.push(Box::new(RectXY { x0: 0., x1: 555., y0: 0., y1: 555., k: 555., material: Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73) }) }));
-    // Light
-    shapes.push(Box::new(RectXZ { x0: 213., x1: 343., z0: 227., z1: 332., k: 554., material: Box::new(DiffuseLight { colour: vec3(15., 15., 15.) }) }));
-    // Boxes
-    let transform = Matrix4::from_translation(vec3(212.5, 82.5, 147.5)) * Matrix4::from_angle_y(Deg(-18.));
-    shapes.push(Box::new(Cuboid::new(transform, vec3(165., 165., 165.), Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73) }))));
-    let transform = Matrix4::from_translation(vec3(347.5, 165., 377.5)) * Matrix4::from_angle_y(Deg(15.));
-    shapes.push(Box::new(Cuboid::new(transform, vec3(165., 330., 165.), Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73) }))));   
-    shapes
+#[derive(ClapArgs)]
+struct MergeArgs {
+    /// Checkpoint files to merge, each pixel's samples weighted by their count
+    #[arg(required = true)]
+    checkpoints: Vec<String>,
+    #[command(flatten)]
+    image: ImageArgs,
 }
-fn camera_cornell_box() -> Camera {
-    raytracing::cameras::util::create_camera(
-        Point3::new(278., 278., -800.),
-        Point3::new(278., 278., 0.),
-        vec3(0., 1., 0.),
-        Deg(40.),
-        0.01,
-        100.,
-        0.,
-    )
+
+// How the final image is written
+#[derive(ClapArgs)]
+struct ImageArgs {
+    /// Output image path, the extension picking the format: png, or exr, hdr and pfm for linear HDR
+    #[arg(short, long, default_value = "output.png")]
+    output: String,
+    /// Store EXR output as 16 bit half floats rather than 32 bit floats
+    #[arg(long)]
+    half: bool,
+    /// Tone mapping for PNG output and the preview: aces, agx, reinhard, extended-reinhard or clamp
+    #[arg(long, default_value = "aces")]
+    tone_map: ToneMapOperator,
+    /// Exposure adjustment in stops, applied before tone mapping
+    #[arg(long, default_value_t = 0.)]
+    exposure: f32,
+    /// Luminance mapped to white by the extended-reinhard tone mapping
+    #[arg(long, default_value_t = 4.)]
+    white_point: f32,
+    /// Denoise the image before writing it (and start the preview denoised, D toggles it), guided by the albedo, emission, normal and depth AOVs and each pixel's variance
+    #[arg(long)]
+    denoise: bool,
+    /// Passes of the denoising filter, each reaching twice as far as the last
+    #[arg(long, default_value_t = 5)]
+    denoise_iterations: u32,
 }
 
-fn render(
-    pixels: &mut [f32],
-    top_left: (usize, usize),
-    bounds: (usize, usize),
-    num_samples: u32,
-    image_width: usize,
-    image_height: usize,
-    view_matrix: &Matrix4<f32>,
-    inv_view_projection_matrix: &Matrix4<f32>,
-    camera: &raytracing::cameras::Camera,
-    shapes: &[BoxedHitable],
-    ray_count: &mut u64,
-)
-{
-    for y in 0..bounds.1 {
-        for x in 0..bounds.0 {
-            let mut colour = Vector3::zero();
-            for _s in 0..num_samples {
-                let sx = ((x + top_left.0) as f32) + random::<f32>();
-                let sy = ((y + top_left.1) as f32) + random::<f32>();
-
-                let ndc = Point3::new(
-                    (sx / (image_width as f32 / 2.)) - 1.,
-                    (-sy / (image_height as f32 / 2.)) + 1.,
-                    0.
-                );
-                let ray_pos = inv_view_projection_matrix.transform_point(ndc);
-                let ray_dir = (ray_pos - camera.eye).normalize();
-
-                // :TODO: Defocus blur - tidy up, move some logic into camera struct
-                let lens_radius = camera.aperture / 2.;
-                let rd = lens_radius * random::random_in_unit_disk();
-                let cam_up = view_matrix.transform_vector(vec3(0., 1., 0.));
-                let cam_right = ray_dir.cross(cam_up);
-                let ray_offset = (cam_up * rd.x) + (cam_right * rd.y);
-                let focus_point = ray_pos + (ray_dir * camera.focal_distance);
-                let ray_pos = ray_pos + ray_offset;
-                let ray_dir = (focus_point - ray_pos).normalize();
-
-                let ray = Ray {
-                    origin: ray_pos,
-                    direction: ray_dir
-                };
-
-                colour += raytracing::tracing::trace(shapes, &ray, 0, ray_count);
-            }
-            colour /= num_samples as f32;
+fn print_stats(iteration: u32, stats: &RenderStats) {
+    let rays_per_second = stats.ray_count as f64 / stats.elapsed_time;
+    let mrays_per_second = rays_per_second / 1000000.;
+    println!("[{}] Mrays/sec: {} (rays: {} elapsed_time: {} active_pixels: {})", iteration, mrays_per_second, stats.ray_count, stats.elapsed_time, stats.active_pixels);
+}
+
+fn tone_mapping(args: &ImageArgs) -> ToneMapping {
+    ToneMapping { operator: args.tone_map, exposure: args.exposure, white_point: args.white_point }
+}
 
-            let Vector3 { x: r, y: g, z: b} = colour;
+fn denoiser(args: &ImageArgs) -> Denoiser {
+    Denoiser { iterations: args.denoise_iterations, ..Denoiser::default() }
+}
 
-            let base = ((y * image_width) + x) * 3;
-            pixels[base] = r;
-            pixels[base + 1] = g;
-            pixels[base + 2] = b;
+fn output_format(path: &str, half: bool) -> OutputFormat {
+    match OutputFormat::from_path(path) {
+        Some(OutputFormat::Exr { .. }) => OutputFormat::Exr { half },
+        Some(format) => format,
+        None => {
+            eprintln!("Unknown output format for '{}' (expected .png, .exr, .hdr or .pfm)", path);
+            process::exit(1);
         }
     }
 }
 
-fn main() {
-    // Set up image output & camera
-    let window_width = 640;
-    let window_height = 400;
-    let image_width = 640;
-    let image_height = 400;
-    let image_aspect = image_width as f32 / image_height as f32;
-    let num_samples = 1;//20;
+// Writes the image along with the requested `aovs`, which go in the same file if it's an EXR and
+// otherwise each in a file of its own. The film may hold other AOVs, just for the denoiser.
+fn write_output(args: &ImageArgs, format: OutputFormat, film: &Film, aovs: &[Aov]) {
+    let dimensions = (film.width as u32, film.height as u32);
+    let denoised = if args.denoise { Some(denoiser(args).denoise(film)) } else { None };
+    let image = denoised.as_ref().unwrap_or(&film.accumulated_image);
+    let aovs: Vec<&AovBuffer> = film.aovs.iter().filter(|buffer| aovs.contains(&buffer.aov)).collect();
+    if let OutputFormat::Exr { half } = format {
+        if !aovs.is_empty() {
+            let mut layers = vec![ExrLayer { name: "", channels: &["R", "G", "B"], values: image }];
+            layers.extend(aovs.iter().map(|buffer| ExrLayer { name: buffer.aov.name(), channels: buffer.aov.channels(), values: &buffer.values }));
+            output::write_exr_layers(&args.output, &layers, dimensions, half).expect("Unable to save image");
+            return;
+        }
+    }
+    output::write_image(&args.output, format, image, dimensions, &tone_mapping(args)).expect("Unable to save image");
+    for buffer in aovs {
+        write_aov(&args.output, format, film, buffer);
+    }
+}
 
-    let mut total_samples = 0.0;
-    let sample_cap = 2000;
+// Writes data rather than radiance, without tone mapping. A PNG is scaled so the largest value is
+// white, the HDR formats keep the values as they are.
+fn write_data_image(path: &str, format: OutputFormat, film: &Film, mut pixels: Vec<f32>) -> Result<(), io::Error> {
+    if format == OutputFormat::Png {
+        let max = pixels.iter().cloned().fold(0., f32::max);
+        let scale = if max > 0. { 1. / max } else { 1. };
+        for v in &mut pixels {
+            *v *= scale;
+        }
+    }
+    let tone_mapping = ToneMapping { operator: ToneMapOperator::Clamp, ..ToneMapping::default() };
+    output::write_image(path, format, &pixels, (film.width as u32, film.height as u32), &tone_mapping)
+}
 
-    let num_pixels = image_width * image_height;
-    let mut accumulated_image: Vec<f32> = vec![0.0; num_pixels * 3];
+fn write_sample_map(path: &str, format: OutputFormat, film: &Film) {
+    let pixels = film.sample_counts().iter().flat_map(|&count| vec![count as f32; 3]).collect();
+    write_data_image(path, format, film, pixels).expect("Unable to save sample map");
+}
 
-    // Build scene
-    // :TODO: Think further about how to represent a collection of hetergenous objects uniformly.
-    //let shapes = scene_test();
-    let shapes = scene_cornell_box();
+// Single channel AOVs are written as grey. In a PNG, albedo is written as it is and normals are
+// mapped from [-1, 1] to [0, 1] as in a normal map, everything else is scaled by its largest value.
+fn write_aov(output: &str, format: OutputFormat, film: &Film, buffer: &AovBuffer) {
+    let extension = Path::new(output).extension().and_then(|e| e.to_str()).unwrap_or("");
+    let path = Path::new(output).with_extension(format!("{}.{}", buffer.aov.name(), extension));
+    let path = path.to_str().expect("Invalid AOV path");
+    let channels = buffer.aov.channels().len();
+    let pixels: Vec<f32> = buffer.values.chunks(channels).flat_map(|v| if channels == 1 { vec![v[0]; 3] } else { v.to_vec() }).collect();
+    let result = match (format, buffer.aov) {
+        (OutputFormat::Png, Aov::Albedo) => output::write_image(path, format, &pixels, (film.width as u32, film.height as u32), &ToneMapping { operator: ToneMapOperator::Clamp, ..ToneMapping::default() }),
+        (OutputFormat::Png, Aov::Normal) | (OutputFormat::Png, Aov::CameraNormal) => {
+            let pixels = pixels.iter().map(|v| v * 0.5 + 0.5).collect();
+            write_data_image(path, OutputFormat::Png, film, pixels)
+        },
+        _ => write_data_image(path, format, film, pixels),
+    };
+    result.expect("Unable to save AOV");
+}
+
+// Identifies what a checkpoint was rendered from: a built-in scene by its name, and a scene file by
+// its contents and those of the files it refers to (a material library that couldn't be read
+// counts as empty).
+fn scene_hashes(scene: &str, assets: &[PathBuf]) -> (u64, u64) {
+    let scene_hash = if scenes::BUILTIN_SCENES.contains(&scene) {
+        checkpoint::hash_bytes(scene.as_bytes())
+    } else {
+        checkpoint::hash_bytes(&fs::read(scene).expect("Unable to read scene"))
+    };
+    let asset_hashes: Vec<u8> = assets.iter()
+        .flat_map(|path| checkpoint::hash_bytes(&fs::read(path).unwrap_or_default()).to_le_bytes().to_vec())
+        .collect();
+    (scene_hash, checkpoint::hash_bytes(&asset_hashes))
+}
+
+fn write_checkpoint(args: &Args, renderer: &Renderer, scene_hashes: (u64, u64), film: &Film, iterations: u32) {
+    if let Some(ref path) = args.checkpoint {
+        let header = CheckpointHeader::new(renderer, scene_hashes.0, scene_hashes.1, iterations);
+        checkpoint::write_checkpoint(path, &header, film).expect("Unable to save checkpoint");
+    }
+}
+
+// Loads the checkpoint to resume from, refusing one that was rendered from anything else or whose
+// samples were taken differently, as they can't be mixed with the ones still to come.
+fn read_checkpoint(args: &Args, renderer: &Renderer, scene_hashes: (u64, u64)) -> (CheckpointHeader, Film) {
+    let path = args.checkpoint.as_ref().expect("--resume requires --checkpoint");
+    let (header, film) = checkpoint::read_checkpoint(path).unwrap_or_else(|err| {
+        eprintln!("Unable to resume: {}", err);
+        process::exit(1);
+    });
+    let expected = CheckpointHeader::new(renderer, scene_hashes.0, scene_hashes.1, header.iterations);
+    let mismatch = header.mismatch(&expected).or_else(|| {
+        if header.seed != expected.seed { Some(format!("was rendered with seed {}, not {}", header.seed, expected.seed)) } else { None }
+    });
+    if let Some(mismatch) = mismatch {
+        eprintln!("Unable to resume: {} {}", path, mismatch);
+        process::exit(1);
+    }
+    (header, film)
+}
+
+fn run_headless(scheduler: &TileScheduler, film: &mut Film, args: &Args, format: OutputFormat, scene_hashes: (u64, u64), mut total_samples: u32) -> u32 {
+    while total_samples < args.max_iterations {
+        let stats = scheduler.accumulate(film);
+        if stats.active_pixels == 0 {
+            println!("All pixels converged");
+            break;
+        }
+        total_samples += 1;
+        print_stats(total_samples, &stats);
 
-    //let mut cam_pos = Point3::new(0., 0.2, 1.75);
+        // Progressively write out the image so long renders can be inspected
+        if args.write_interval > 0 && total_samples.is_multiple_of(args.write_interval) && total_samples < args.max_iterations {
+            write_output(&args.image, format, film, &args.aovs);
+        }
+        if args.checkpoint_interval > 0 && total_samples.is_multiple_of(args.checkpoint_interval) && total_samples < args.max_iterations {
+            write_checkpoint(args, &scheduler.renderer, scene_hashes, film, total_samples);
+        }
+    }
+    total_samples
+}
 
+#[cfg(not(feature = "sdl"))]
+fn run_window(scheduler: &TileScheduler, film: &mut Film, args: &Args, format: OutputFormat, scene_hashes: (u64, u64), total_samples: u32) -> u32 {
+    println!("Built without the 'sdl' feature, rendering without a preview window");
+    run_headless(scheduler, film, args, format, scene_hashes, total_samples)
+}
 
+#[cfg(feature = "sdl")]
+fn run_window(scheduler: &TileScheduler, film: &mut Film, args: &Args, _format: OutputFormat, scene_hashes: (u64, u64), mut total_samples: u32) -> u32 {
+    let renderer = &scheduler.renderer;
+    let image_width = renderer.settings.image_width;
+    let image_height = renderer.settings.image_height;
+    let window_width = image_width as u32;
+    let window_height = image_height as u32;
+    let tone_mapping = tone_mapping(&args.image);
+    let denoiser = denoiser(&args.image);
+    let mut denoise_preview = args.image.denoise;
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
-    let window = video_subsystem.window("rust-sdl2 demo: Video", window_width, window_height)
+    let window = video_subsystem.window("rs-pathtracer", window_width, window_height)
         .position_centered()
         //.opengl()
         .build()
@@ -182,76 +341,41 @@ fn main() {
     let mut event_pump = sdl_context.event_pump().unwrap();
 
     'running: loop {
+        let mut toggled = false;
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit {..} 
+                Event::Quit {..}
                 | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                Event::KeyDown { keycode: Some(Keycode::D), .. } => {
+                    denoise_preview = !denoise_preview;
+                    toggled = true;
+                },
                 _ => {}
             }
         }
-        // The rest of the game loop goes here...
-
-        // let rotation = Matrix3::from_angle_y(Deg(1.));
-        // cam_pos = rotation.transform_point(cam_pos);
-        // let camera = raytracing::cameras::util::create_camera(
-        //     cam_pos,
-        //     Point3::new(0., 0., 0.),
-        //     vec3(0., 1., 0.),
-        //     Deg(60.),
-        //     0.01,
-        //     100.,
-        //     0.2
-        // );
-        let camera = camera_cornell_box();
-        let view_matrix = Matrix4::look_at(camera.eye, camera.target, camera.up);
-        let projection_matrix = perspective(camera.fov, image_aspect, camera.near, camera.far);
-        let view_projection_matrix = projection_matrix * view_matrix;
-        let inv_view_projection_matrix = view_projection_matrix.inverse_transform().unwrap();
-
-        let mut image: Vec<f32> = vec![0.0; num_pixels * 3];
-
-        let thread_count = num_cpus::get();
-        let rows_per_band = image_height / thread_count;
-
-        let start_time = Instant::now();
-        let mut ray_counts = vec![0; thread_count];
-
-        {
-            let bands: Vec<&mut [f32]> = image.chunks_mut(rows_per_band * image_width * 3).collect();
-            crossbeam::scope(|scope| {
-                let camera_ref = &camera;
-                let shapes_ref = &shapes;
-                for (i, band, ray_count) in itertools::multizip((0..thread_count, bands.into_iter(), &mut ray_counts)) {
-                    let top = rows_per_band * i;
-                    let height = band.len() / (image_width * 3);
-                    let top_left = (0, top);
-                    let band_bounds = (image_width, height);
-                    scope.spawn(move || {
-                        render(band, top_left, band_bounds, num_samples, image_width, image_height, &view_matrix, &inv_view_projection_matrix, camera_ref, shapes_ref, ray_count);
-                    });
-                }
-            });
-        }
 
-        for p in 0..(num_pixels * 3) as usize {
-            accumulated_image[p] = (accumulated_image[p] * (total_samples / (total_samples + 1.0))) + (image[p] * (1.0 / (total_samples + 1.0)));
+        // Once every pixel has converged the preview just stays as it is, unless toggled
+        let stats = scheduler.accumulate(film);
+        let converged = stats.active_pixels == 0;
+        if converged && !toggled {
+            thread::sleep(Duration::from_millis(50));
+            continue;
         }
-        total_samples += 1.0;
 
+        let denoised = if denoise_preview { Some(denoiser.denoise(film)) } else { None };
+        let image = denoised.as_ref().unwrap_or(&film.accumulated_image);
 
         let mut texture = texture_creator.create_texture_streaming(
             PixelFormatEnum::RGB24, image_width as u32, image_height as u32).unwrap();
         texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for y in 0..image_height as usize {
-                for x in 0..image_width as usize {
+            for y in 0..image_height {
+                for x in 0..image_width {
                     let offset = y*pitch + x*3;
-                    let offset_in = y*(image_width * 3) as usize + x*3;
-                    // Gamma correct & convert to 8bpp
-                    buffer[offset] = (accumulated_image[offset_in].min(1.0).sqrt() * 255.) as u8;
-                    buffer[offset + 1] = (accumulated_image[offset_in + 1].min(1.0).sqrt() * 255.) as u8;
-                    buffer[offset + 2] = (accumulated_image[offset_in + 2].min(1.0).sqrt() * 255.) as u8;
+                    let offset_in = y*(image_width * 3) + x*3;
+                    let pixel = &image[offset_in..(offset_in + 3)];
+                    buffer[offset..(offset + 3)].copy_from_slice(&tone_mapping.to_srgb8(pixel[0], pixel[1], pixel[2]));
                 }
             }
         }).unwrap();
@@ -260,30 +384,131 @@ fn main() {
         canvas.copy(&texture, None, Some(Rect::new(0, 0, window_width, window_height))).unwrap();
         canvas.present();
 
-        let current_time = Instant::now();
-        let duration = current_time.duration_since(start_time);
-        let elapsed_time = duration.as_secs() as f64 + (duration.subsec_nanos() as f64 * 1e-9);
-        let ray_count = ray_counts.iter().fold(0,|a, &b| a + b);
-        let rays_per_second = ray_count as f64 / elapsed_time;
-        let mrays_per_second = rays_per_second / 1000000.;
-        println!("Mrays/sec: {} (rays: {} elapsed_time: {})", mrays_per_second, ray_count, elapsed_time);
+        if converged {
+            continue;
+        }
+        total_samples += 1;
+        print_stats(total_samples, &stats);
+
+        if args.checkpoint_interval > 0 && total_samples.is_multiple_of(args.checkpoint_interval) && total_samples < args.max_iterations {
+            write_checkpoint(args, renderer, scene_hashes, film, total_samples);
+        }
 
-        if total_samples as u32 >= sample_cap {
+        if total_samples >= args.max_iterations {
             break 'running;
         }
     }
+    total_samples
+}
+
+// The requested AOVs, and those guiding the denoiser if it's to be used.
+fn render_aovs(args: &Args) -> Vec<Aov> {
+    let mut aovs = args.aovs.clone();
+    if args.image.denoise {
+        for aov in &[Aov::Albedo, Aov::Emission, Aov::Normal, Aov::Depth] {
+            if !aovs.contains(aov) {
+                aovs.push(*aov);
+            }
+        }
+    }
+    aovs
+}
+
+fn merge(args: &MergeArgs) {
+    let format = output_format(&args.image.output, args.image.half);
+    let (headers, film) = checkpoint::merge_checkpoints(&args.checkpoints).unwrap_or_else(|err| {
+        eprintln!("Unable to merge: {}", err);
+        process::exit(1);
+    });
+
+    // Renders with the same seed took the same samples, so merging them adds nothing
+    for (i, header) in headers.iter().enumerate() {
+        if let Some(j) = headers[..i].iter().position(|other| other.seed == header.seed) {
+            eprintln!("Warning: {} and {} were both rendered with seed {}", args.checkpoints[j], args.checkpoints[i], header.seed);
+        }
+    }
+
+    let samples: u64 = film.pixels.iter().map(|p| p.count as u64).sum();
+    println!("Merged {} checkpoints, {:.1} samples per pixel", headers.len(), samples as f64 / film.pixels.len().max(1) as f64);
+    write_output(&args.image, format, &film, &[]);
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(Command::Merge(ref merge_args)) = args.command {
+        merge(merge_args);
+        return;
+    }
+
+    let scene = match scenes::builtin(&args.scene) {
+        Some(scene) => scene,
+        None if Path::new(&args.scene).is_file() => {
+            scenes::load_scene(&args.scene).unwrap_or_else(|err| {
+                eprintln!("{}: {}", args.scene, err);
+                process::exit(1);
+            })
+        },
+        None => {
+            eprintln!("Unknown scene '{}' (not a file or one of: {})", args.scene, scenes::BUILTIN_SCENES.join(", "));
+            process::exit(1);
+        }
+    };
+
+    let format = output_format(&args.image.output, args.image.half);
+    let sample_map_format = args.sample_map.as_ref().map(|path| output_format(path, args.image.half));
+
+    let scene_hashes = scene_hashes(&args.scene, &scene.assets);
+
+    let mut settings = RenderSettings {
+        image_width: args.width,
+        image_height: args.height,
+        num_samples: args.samples,
+        tile_size: args.tile_size,
+        tile_order: args.tile_order,
+        integrator: args.integrator,
+        sampler: args.sampler,
+        seed: args.seed,
+        adaptive_threshold: args.adaptive_threshold,
+        adaptive_min_samples: args.adaptive_min_samples,
+        aovs: render_aovs(&args),
+        path_limits: PathLimits {
+            max_diffuse_depth: args.max_diffuse_depth,
+            max_specular_depth: args.max_specular_depth,
+            max_transmission_depth: args.max_transmission_depth,
+            roulette_depth: args.roulette_depth,
+        },
+        spectral: args.spectral,
+        ..RenderSettings::default()
+    };
+    if let Some(threads) = args.threads {
+        settings.thread_count = threads;
+    }
+
+    let renderer = Arc::new(Renderer::new(settings, scene.shapes, scene.material_indices, scene.camera, scene.environment));
+    let resumed = if args.resume { Some(read_checkpoint(&args, &renderer, scene_hashes)) } else { None };
+    let scheduler = TileScheduler::new(renderer.clone());
+    let (first_iteration, mut film) = match resumed {
+        Some((header, film)) => {
+            println!("Resuming from iteration {}", header.iterations);
+            (header.iterations, film)
+        },
+        None => (0, Film::new(renderer.settings.image_width, renderer.settings.image_height)),
+    };
+    film.add_aovs(&renderer.settings.aovs);
+
+    let total_samples = if args.no_window {
+        run_headless(&scheduler, &mut film, &args, format, scene_hashes, first_iteration)
+    } else {
+        run_window(&scheduler, &mut film, &args, format, scene_hashes, first_iteration)
+    };
 
     println!("Total iterations: {}", total_samples);
+    write_checkpoint(&args, &renderer, scene_hashes, &film, total_samples);
 
     // Write out final image
-    let mut converted_image: Vec<u8> = vec![0; num_pixels * 3];
-    for y in 0..image_height as usize {
-        for x in 0..image_width as usize {
-            let offset = y*(image_width * 3) as usize + x*3;
-            converted_image[offset] = (accumulated_image[offset].min(1.0).sqrt() * 255.) as u8;
-            converted_image[offset + 1] = (accumulated_image[offset + 1].min(1.0).sqrt() * 255.) as u8;
-            converted_image[offset + 2] = (accumulated_image[offset + 2].min(1.0).sqrt() * 255.) as u8;
-        }
+    write_output(&args.image, format, &film, &args.aovs);
+    if let (Some(path), Some(format)) = (args.sample_map.as_ref(), sample_map_format) {
+        write_sample_map(path, format, &film);
     }
-    write_png_rgb8("output.png", converted_image.as_slice(), (image_width as u32, image_height as u32)).expect("Unable to save PNG");
-}
\ No newline at end of file
+}
+
@@ -0,0 +1,249 @@
+use cgmath::*;
+use raytracing::{Aabb, BoxedHitable, Hit, Hitable, HitableCollection, Interval, Ray};
+
+const MAX_SHAPES_PER_LEAF: usize = 4;
+const NUM_BUCKETS: usize = 12;
+// Cost of visiting an interior node relative to intersecting a shape.
+const TRAVERSAL_COST: f32 = 0.125;
+// Beyond this depth nodes are split at the median, which bounds the traversal stack size.
+const MAX_SAH_DEPTH: usize = 32;
+const MAX_DEPTH: usize = 64;
+
+enum NodeContent {
+    Leaf { first: usize, count: usize },
+    // The first child immediately follows its parent.
+    Interior { second_child: usize, axis: usize },
+}
+
+struct Node {
+    bounds: Aabb,
+    content: NodeContent,
+}
+
+struct BuildItem {
+    index: usize,
+    bounds: Aabb,
+    centroid: Point3<f32>,
+}
+
+// Bounding volume hierarchy built with the surface area heuristic. Shapes without a bounding box
+// (infinite planes) are kept in a separate list and tested against every ray.
+pub struct Bvh {
+    shapes: HitableCollection,
+    unbounded: HitableCollection,
+    // Where each of the shapes (and unbounded shapes) was in the collection the BVH was built from
+    indices: Vec<usize>,
+    unbounded_indices: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    pub fn new(shapes: HitableCollection) -> Bvh {
+        let mut bounded: Vec<Option<BoxedHitable>> = Vec::new();
+        let mut bounded_indices = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut unbounded_indices = Vec::new();
+        let mut items = Vec::new();
+        for (index, shape) in shapes.into_iter().enumerate() {
+            match shape.bounding_box() {
+                Some(bounds) => {
+                    items.push(BuildItem { index: bounded.len(), bounds, centroid: bounds.centroid() });
+                    bounded.push(Some(shape));
+                    bounded_indices.push(index);
+                },
+                None => {
+                    unbounded.push(shape);
+                    unbounded_indices.push(index);
+                },
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            build(&mut nodes, &mut items, 0, 0);
+        }
+
+        // Reorder the shapes so each leaf references a contiguous range
+        let shapes = items.iter().map(|item| bounded[item.index].take().unwrap()).collect();
+        let indices = items.iter().map(|item| bounded_indices[item.index]).collect();
+        Bvh { shapes, unbounded, indices, unbounded_indices, nodes }
+    }
+
+    pub fn shapes(&self) -> &[BoxedHitable] {
+        &self.shapes
+    }
+
+    // Where each of `shapes()` was in the collection the BVH was built from
+    pub fn shape_indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    pub fn unbounded_shapes(&self) -> &[BoxedHitable] {
+        &self.unbounded
+    }
+}
+
+fn partition<F: Fn(&BuildItem) -> bool>(items: &mut [BuildItem], predicate: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+fn split_at_median(items: &mut [BuildItem], axis: usize) -> usize {
+    items.sort_by(|a, b| a.centroid[axis].partial_cmp(&b.centroid[axis]).unwrap());
+    items.len() / 2
+}
+
+// Picks a split using the surface area heuristic, or returns None if a leaf is cheaper.
+fn split_with_sah(items: &mut [BuildItem], bounds: &Aabb, centroid_bounds: &Aabb, axis: usize) -> Option<usize> {
+    let axis_min = centroid_bounds.min[axis];
+    let axis_extent = centroid_bounds.extent()[axis];
+    let bucket_index = |item: &BuildItem| {
+        let b = (NUM_BUCKETS as f32 * ((item.centroid[axis] - axis_min) / axis_extent)) as usize;
+        b.min(NUM_BUCKETS - 1)
+    };
+
+    let mut counts = [0usize; NUM_BUCKETS];
+    let mut bucket_bounds: [Option<Aabb>; NUM_BUCKETS] = [None; NUM_BUCKETS];
+    for item in items.iter() {
+        let b = bucket_index(item);
+        counts[b] += 1;
+        bucket_bounds[b] = Some(match bucket_bounds[b] {
+            Some(bb) => bb.union(&item.bounds),
+            None => item.bounds,
+        });
+    }
+
+    let merge = |range: &[Option<Aabb>]| range.iter().fold(None, |acc: Option<Aabb>, b| match (acc, *b) {
+        (Some(acc), Some(b)) => Some(acc.union(&b)),
+        (acc, b) => acc.or(b),
+    });
+
+    let mut best_split = 0;
+    let mut best_cost = f32::MAX;
+    for split in 0..(NUM_BUCKETS - 1) {
+        let count_below: usize = counts[..=split].iter().sum();
+        let count_above: usize = counts[(split + 1)..].iter().sum();
+        let area_below = merge(&bucket_bounds[..=split]).map_or(0., |b| b.surface_area());
+        let area_above = merge(&bucket_bounds[(split + 1)..]).map_or(0., |b| b.surface_area());
+        let cost = TRAVERSAL_COST + (count_below as f32 * area_below + count_above as f32 * area_above) / bounds.surface_area();
+        if cost < best_cost {
+            best_cost = cost;
+            best_split = split;
+        }
+    }
+
+    let leaf_cost = items.len() as f32;
+    if items.len() > MAX_SHAPES_PER_LEAF || best_cost < leaf_cost {
+        Some(partition(items, |item| bucket_index(item) <= best_split))
+    } else {
+        None
+    }
+}
+
+fn build(nodes: &mut Vec<Node>, items: &mut [BuildItem], first: usize, depth: usize) -> usize {
+    let bounds = items[1..].iter().fold(items[0].bounds, |b, item| b.union(&item.bounds));
+    let node_index = nodes.len();
+    nodes.push(Node { bounds, content: NodeContent::Leaf { first, count: items.len() } });
+    if items.len() == 1 {
+        return node_index;
+    }
+
+    let centroid_bounds = items[1..].iter().fold(Aabb { min: items[0].centroid, max: items[0].centroid }, |b, item| b.include(item.centroid));
+    let extent = centroid_bounds.extent();
+    let axis = if extent.x > extent.y && extent.x > extent.z { 0 } else if extent.y > extent.z { 1 } else { 2 };
+    if extent[axis] <= 0. {
+        // All centroids coincide, nothing to gain from splitting
+        return node_index;
+    }
+
+    let mid = if depth < MAX_SAH_DEPTH {
+        match split_with_sah(items, &bounds, &centroid_bounds, axis) {
+            Some(mid) => mid,
+            None => return node_index,
+        }
+    } else {
+        split_at_median(items, axis)
+    };
+    let mid = if mid == 0 || mid == items.len() { split_at_median(items, axis) } else { mid };
+
+    let (below, above) = items.split_at_mut(mid);
+    build(nodes, below, first, depth + 1);
+    let second_child = build(nodes, above, first + mid, depth + 1);
+    nodes[node_index].content = NodeContent::Interior { second_child, axis };
+    node_index
+}
+
+impl Bvh {
+    // The closest hit, along with where the shape hit was in the collection the BVH was built from.
+    pub fn hit_with_index(&self, ray: &Ray, interval: &Interval) -> Option<(usize, Hit<'_>)> {
+        let mut hit_result = None;
+        let mut closest = interval.max;
+        for (shape, &index) in self.unbounded.iter().zip(self.unbounded_indices.iter()) {
+            if let Some(hit) = shape.hit(ray, &Interval { min: interval.min, max: closest }) {
+                closest = hit.distance;
+                hit_result = Some((index, hit));
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit_result;
+        }
+
+        let inv_direction = vec3(1. / ray.direction.x, 1. / ray.direction.y, 1. / ray.direction.z);
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node.bounds.hit(&ray.origin, &inv_direction, &Interval { min: interval.min, max: closest }) {
+                match node.content {
+                    NodeContent::Leaf { first, count } => {
+                        for (shape, &index) in self.shapes[first..(first + count)].iter().zip(self.indices[first..(first + count)].iter()) {
+                            if let Some(hit) = shape.hit(ray, &Interval { min: interval.min, max: closest }) {
+                                closest = hit.distance;
+                                hit_result = Some((index, hit));
+                            }
+                        }
+                    },
+                    NodeContent::Interior { second_child, axis } => {
+                        // Visit the nearer child first so more of the far one can be culled
+                        if inv_direction[axis] < 0. {
+                            stack[stack_size] = node_index + 1;
+                            node_index = second_child;
+                        } else {
+                            stack[stack_size] = second_child;
+                            node_index += 1;
+                        }
+                        stack_size += 1;
+                        continue;
+                    },
+                }
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size];
+        }
+        hit_result
+    }
+}
+
+impl Hitable for Bvh {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
+        self.hit_with_index(ray, interval).map(|(_, hit)| hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.nodes.first().map(|node| node.bounds)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,2 @@
+mod bvh;
+pub use self::bvh::Bvh;
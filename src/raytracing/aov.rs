@@ -0,0 +1,129 @@
+use cgmath::*;
+use std::str::FromStr;
+
+// Arbitrary output variables: what the primary rays first hit, written alongside the image for
+// denoising and compositing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    // Reflectance of the surface
+    Albedo,
+    // Light emitted by the surface
+    Emission,
+    // Shading normal in world space
+    Normal,
+    // Shading normal in camera space (x right, y up, z towards the camera)
+    CameraNormal,
+    // Distance along the camera's view direction
+    Depth,
+    // World space position
+    Position,
+    // Shapes and materials are numbered from 1 in the order they appear in the scene
+    ObjectIndex,
+    MaterialIndex,
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Aov, String> {
+        match s {
+            "albedo" => Ok(Aov::Albedo),
+            "emission" => Ok(Aov::Emission),
+            "normal" => Ok(Aov::Normal),
+            "camera-normal" => Ok(Aov::CameraNormal),
+            "depth" => Ok(Aov::Depth),
+            "position" => Ok(Aov::Position),
+            "object-index" => Ok(Aov::ObjectIndex),
+            "material-index" => Ok(Aov::MaterialIndex),
+            _ => Err(format!("unknown AOV '{}' (expected albedo, emission, normal, camera-normal, depth, position, object-index or material-index)", s)),
+        }
+    }
+}
+
+impl Aov {
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Emission => "emission",
+            Aov::Normal => "normal",
+            Aov::CameraNormal => "camera-normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectIndex => "object-index",
+            Aov::MaterialIndex => "material-index",
+        }
+    }
+
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Albedo | Aov::Emission => &["R", "G", "B"],
+            Aov::Normal | Aov::CameraNormal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectIndex | Aov::MaterialIndex => &["id"],
+        }
+    }
+
+    // Indices are kept from a pixel's first sample rather than averaged, which would give
+    // meaningless values along the edges of shapes.
+    pub fn is_averaged(self) -> bool {
+        !matches!(self, Aov::ObjectIndex | Aov::MaterialIndex)
+    }
+}
+
+// Everything the AOVs are taken from at a primary ray's hit. Rays that hit nothing leave every AOV
+// at zero.
+pub struct PrimaryHit {
+    pub albedo: Vector3<f32>,
+    pub emission: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub camera_normal: Vector3<f32>,
+    pub depth: f32,
+    pub position: Point3<f32>,
+    pub object_index: u32,
+    pub material_index: u32,
+}
+
+impl PrimaryHit {
+    pub fn value(&self, aov: Aov) -> [f32; 3] {
+        match aov {
+            Aov::Albedo => self.albedo.into(),
+            Aov::Emission => self.emission.into(),
+            Aov::Normal => self.normal.into(),
+            Aov::CameraNormal => self.camera_normal.into(),
+            Aov::Depth => [self.depth, 0., 0.],
+            Aov::Position => self.position.into(),
+            Aov::ObjectIndex => [self.object_index as f32, 0., 0.],
+            Aov::MaterialIndex => [self.material_index as f32, 0., 0.],
+        }
+    }
+}
+
+// One AOV over the pixels of an image or a tile, row by row with the AOV's channels interleaved.
+#[derive(Clone, Debug)]
+pub struct AovBuffer {
+    pub aov: Aov,
+    pub values: Vec<f32>,
+}
+
+impl AovBuffer {
+    pub fn new(aov: Aov, pixel_count: usize) -> AovBuffer {
+        AovBuffer { aov, values: vec![0.; pixel_count * aov.channels().len()] }
+    }
+
+    pub fn pixel(&self, pixel: usize) -> &[f32] {
+        let channels = self.aov.channels().len();
+        &self.values[(pixel * channels)..((pixel + 1) * channels)]
+    }
+
+    // Sums a sample's value into the pixel, or for indices keeps it if it's the pixel's first.
+    pub fn add(&mut self, pixel: usize, primary_hit: Option<&PrimaryHit>, first_sample: bool) {
+        if !self.aov.is_averaged() && !first_sample {
+            return;
+        }
+        let value = primary_hit.map_or([0.; 3], |hit| hit.value(self.aov));
+        let channels = self.aov.channels().len();
+        for (v, x) in self.values[(pixel * channels)..((pixel + 1) * channels)].iter_mut().zip(value.iter()) {
+            *v += x;
+        }
+    }
+}
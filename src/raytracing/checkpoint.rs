@@ -0,0 +1,296 @@
+use cgmath::*;
+use raytracing::{Camera, Film, Integrator, PixelStats, Renderer};
+use raytracing::samplers::SamplerType;
+use raytracing::tracing::PathLimits;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+const MAGIC: &[u8; 8] = b"RSPTCKPT";
+const VERSION: u32 = 3;
+// The magic number and version, the size, the hashes and seed, the settings and the iterations
+const HEADER_SIZE: usize = 8 + 4 + 2 * 4 + 4 * 8 + 10 * 4 + 4;
+// Mean RGB and M2 as f32, and the sample count as u32
+const PIXEL_SIZE: usize = 5 * 4;
+
+// In the order they're numbered in a checkpoint
+const SAMPLERS: [SamplerType; 4] = [SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol];
+const INTEGRATORS: [Integrator; 3] = [Integrator::BruteForce, Integrator::LightSampling, Integrator::Mis];
+
+// What a checkpoint was rendered from. Samples are numbered per pixel and drawn from the seed, so
+// the seed and the film's sample counts are all it takes to carry on exactly where it stopped, as
+// long as the samples are still taken the same way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CheckpointHeader {
+    pub width: u32,
+    pub height: u32,
+    pub scene_hash: u64,
+    // Of the contents of the files the scene refers to
+    pub assets_hash: u64,
+    pub camera_hash: u64,
+    pub seed: u64,
+    pub sampler: SamplerType,
+    pub integrator: Integrator,
+    // Which the stratified sampler divides its strata between
+    pub samples_per_pass: u32,
+    pub adaptive_threshold: Option<f32>,
+    pub adaptive_min_samples: u32,
+    pub path_limits: PathLimits,
+    pub spectral: bool,
+    pub iterations: u32,
+}
+
+impl CheckpointHeader {
+    pub fn new(renderer: &Renderer, scene_hash: u64, assets_hash: u64, iterations: u32) -> CheckpointHeader {
+        let settings = &renderer.settings;
+        CheckpointHeader {
+            width: settings.image_width as u32,
+            height: settings.image_height as u32,
+            scene_hash,
+            assets_hash,
+            camera_hash: camera_hash(&renderer.camera),
+            seed: settings.seed,
+            sampler: settings.sampler,
+            integrator: settings.integrator,
+            samples_per_pass: settings.num_samples,
+            adaptive_threshold: settings.adaptive_threshold,
+            adaptive_min_samples: settings.adaptive_min_samples,
+            path_limits: settings.path_limits,
+            spectral: settings.spectral,
+            iterations,
+        }
+    }
+
+    // How this checkpoint differs from `other` in what was rendered or how its samples were taken,
+    // which would stop them being accumulated together. The seed and iterations are left out.
+    pub fn mismatch(&self, other: &CheckpointHeader) -> Option<String> {
+        if (self.width, self.height) != (other.width, other.height) {
+            Some(format!("is {}x{}, not {}x{}", self.width, self.height, other.width, other.height))
+        } else if self.scene_hash != other.scene_hash {
+            Some("is of a different scene".to_string())
+        } else if self.assets_hash != other.assets_hash {
+            Some("is of a scene with different meshes or images".to_string())
+        } else if self.camera_hash != other.camera_hash {
+            Some("has a different camera".to_string())
+        } else if self.sampler != other.sampler {
+            Some(format!("was rendered with the {:?} sampler, not {:?}", self.sampler, other.sampler))
+        } else if self.integrator != other.integrator {
+            Some(format!("was rendered with the {:?} integrator, not {:?}", self.integrator, other.integrator))
+        } else if self.samples_per_pass != other.samples_per_pass {
+            Some(format!("was rendered with {} samples per pass, not {}", self.samples_per_pass, other.samples_per_pass))
+        } else if (self.adaptive_threshold, self.adaptive_min_samples) != (other.adaptive_threshold, other.adaptive_min_samples) {
+            Some("was rendered with different adaptive sampling settings".to_string())
+        } else if self.path_limits != other.path_limits {
+            Some("was rendered with different path depth limits".to_string())
+        } else if self.spectral != other.spectral {
+            Some(format!("was rendered {} --spectral", if self.spectral { "with" } else { "without" }))
+        } else {
+            None
+        }
+    }
+}
+
+// FNV-1a, which unlike the standard library's hasher is guaranteed to stay the same between builds.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+pub fn camera_hash(camera: &Camera) -> u64 {
+    let values = [
+        camera.eye.x, camera.eye.y, camera.eye.z,
+        camera.target.x, camera.target.y, camera.target.z,
+        camera.up.x, camera.up.y, camera.up.z,
+        camera.fov.0, camera.near, camera.far, camera.aperture, camera.focal_distance,
+    ];
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_bits().to_le_bytes().to_vec()).collect();
+    hash_bytes(&bytes)
+}
+
+// Writes the raw accumulation, going through a temporary file so that a crash while writing leaves
+// the previous checkpoint intact.
+pub fn write_checkpoint(filename: &str, header: &CheckpointHeader, film: &Film) -> io::Result<()> {
+    let temporary = format!("{}.tmp", filename);
+    {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&header.width.to_le_bytes())?;
+        writer.write_all(&header.height.to_le_bytes())?;
+        for value in &[header.scene_hash, header.assets_hash, header.camera_hash, header.seed] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        let sampler = SAMPLERS.iter().position(|&sampler| sampler == header.sampler).unwrap() as u32;
+        let integrator = INTEGRATORS.iter().position(|&integrator| integrator == header.integrator).unwrap() as u32;
+        // No threshold is stored as NaN
+        let adaptive_threshold = header.adaptive_threshold.unwrap_or(f32::NAN).to_bits();
+        let limits = &header.path_limits;
+        let settings = [
+            sampler, integrator, header.samples_per_pass, adaptive_threshold, header.adaptive_min_samples,
+            limits.max_diffuse_depth, limits.max_specular_depth, limits.max_transmission_depth, limits.roulette_depth,
+            header.spectral as u32,
+        ];
+        for setting in &settings {
+            writer.write_all(&setting.to_le_bytes())?;
+        }
+        writer.write_all(&header.iterations.to_le_bytes())?;
+        for pixel in &film.pixels {
+            for v in &[pixel.mean.x, pixel.mean.y, pixel.mean.z, pixel.m2] {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+            writer.write_all(&pixel.count.to_le_bytes())?;
+        }
+        writer.flush()?;
+    }
+    fs::rename(&temporary, filename)
+}
+
+pub fn read_checkpoint(filename: &str) -> io::Result<(CheckpointHeader, Film)> {
+    let bytes = fs::read(filename)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", filename, message));
+    if bytes.len() < HEADER_SIZE || &bytes[0..8] != MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+    let u64_at = |offset: usize| (u32_at(offset) as u64) | ((u32_at(offset + 4) as u64) << 32);
+    if u32_at(8) != VERSION {
+        return Err(invalid(&format!("unsupported checkpoint version {}", u32_at(8))));
+    }
+    let sampler = *SAMPLERS.get(u32_at(52) as usize).ok_or_else(|| invalid(&format!("unknown sampler {}", u32_at(52))))?;
+    let integrator = *INTEGRATORS.get(u32_at(56) as usize).ok_or_else(|| invalid(&format!("unknown integrator {}", u32_at(56))))?;
+    let adaptive_threshold = f32::from_bits(u32_at(64));
+    let header = CheckpointHeader {
+        width: u32_at(12),
+        height: u32_at(16),
+        scene_hash: u64_at(20),
+        assets_hash: u64_at(28),
+        camera_hash: u64_at(36),
+        seed: u64_at(44),
+        sampler,
+        integrator,
+        samples_per_pass: u32_at(60),
+        adaptive_threshold: if adaptive_threshold.is_nan() { None } else { Some(adaptive_threshold) },
+        adaptive_min_samples: u32_at(68),
+        path_limits: PathLimits {
+            max_diffuse_depth: u32_at(72),
+            max_specular_depth: u32_at(76),
+            max_transmission_depth: u32_at(80),
+            roulette_depth: u32_at(84),
+        },
+        spectral: u32_at(88) != 0,
+        iterations: u32_at(92),
+    };
+
+    let (width, height) = (header.width as usize, header.height as usize);
+    if bytes.len() != HEADER_SIZE + width * height * PIXEL_SIZE {
+        return Err(invalid("truncated checkpoint"));
+    }
+    let pixels = bytes[HEADER_SIZE..].chunks(PIXEL_SIZE).map(|p| {
+        let f32_at = |offset: usize| f32::from_bits(u32::from_le_bytes([p[offset], p[offset + 1], p[offset + 2], p[offset + 3]]));
+        let count = u32::from_le_bytes([p[16], p[17], p[18], p[19]]);
+        PixelStats { mean: vec3(f32_at(0), f32_at(4), f32_at(8)), m2: f32_at(12), count }
+    }).collect();
+    Ok((header, Film::from_pixels(width, height, pixels)))
+}
+
+// Combines checkpoints of the same render made separately (on other machines, with other seeds) into
+// one film. They must all be of the same scene, seen by the same camera at the same resolution, and
+// have been rendered with the same settings other than the seed.
+pub fn merge_checkpoints(filenames: &[String]) -> io::Result<(Vec<CheckpointHeader>, Film)> {
+    let mut headers: Vec<CheckpointHeader> = Vec::with_capacity(filenames.len());
+    let mut merged: Option<Film> = None;
+    for filename in filenames {
+        let (header, film) = read_checkpoint(filename)?;
+        if let Some(first) = headers.first() {
+            if let Some(mismatch) = header.mismatch(first) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} doesn't match {}: it {}", filename, filenames[0], mismatch)));
+            }
+        }
+        headers.push(header);
+        match merged {
+            Some(ref mut merged) => merged.merge(&film),
+            None => merged = Some(film),
+        }
+    }
+    merged.map(|film| (headers, film)).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no checkpoints to merge"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn header() -> CheckpointHeader {
+        CheckpointHeader {
+            width: 3,
+            height: 2,
+            scene_hash: hash_bytes(b"scene"),
+            assets_hash: hash_bytes(b"assets"),
+            camera_hash: hash_bytes(b"camera"),
+            seed: 0x0123_4567_89ab_cdef,
+            sampler: SamplerType::Sobol,
+            integrator: Integrator::Mis,
+            samples_per_pass: 4,
+            adaptive_threshold: Some(0.01),
+            adaptive_min_samples: 16,
+            path_limits: PathLimits { max_diffuse_depth: 5, max_specular_depth: 10, max_transmission_depth: 20, roulette_depth: 2 },
+            spectral: true,
+            iterations: 42,
+        }
+    }
+
+    fn film(header: &CheckpointHeader) -> Film {
+        let mut film = Film::new(header.width as usize, header.height as usize);
+        for (i, pixel) in film.pixels.iter_mut().enumerate() {
+            for sample in 0..i {
+                pixel.add(vec3(i as f32, sample as f32 * 0.5, 1.));
+            }
+        }
+        film
+    }
+
+    fn temporary_path(name: &str) -> String {
+        env::temp_dir().join(format!("checkpoint_test_{}_{}", process::id(), name)).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn checkpoints_read_back_as_written() {
+        let without_threshold = CheckpointHeader { adaptive_threshold: None, spectral: false, ..header() };
+        for (i, header) in [header(), without_threshold].iter().enumerate() {
+            let filename = temporary_path(&format!("round_trip_{}", i));
+            let film = film(header);
+            write_checkpoint(&filename, header, &film).unwrap();
+            let (read_header, read_film) = read_checkpoint(&filename).unwrap();
+            fs::remove_file(&filename).unwrap();
+
+            assert_eq!(read_header, *header);
+            assert_eq!((read_film.width, read_film.height), (film.width, film.height));
+            for (read, written) in read_film.pixels.iter().zip(&film.pixels) {
+                assert_eq!((read.mean, read.m2, read.count), (written.mean, written.m2, written.count));
+            }
+        }
+    }
+
+    #[test]
+    fn checkpoints_with_different_settings_are_not_merged() {
+        let other_seed = CheckpointHeader { seed: 1, ..header() };
+        let other_limits = CheckpointHeader { path_limits: PathLimits { roulette_depth: 3, ..header().path_limits }, ..header() };
+        let filenames: Vec<String> = ["first", "other_seed", "other_limits"].iter().map(|name| temporary_path(name)).collect();
+        for (filename, header) in filenames.iter().zip(&[header(), other_seed, other_limits]) {
+            write_checkpoint(filename, header, &film(header)).unwrap();
+        }
+        let merged = merge_checkpoints(&filenames[..2]);
+        let mismatched = merge_checkpoints(&[filenames[0].clone(), filenames[2].clone()]);
+        for filename in &filenames {
+            fs::remove_file(filename).unwrap();
+        }
+
+        let (headers, film) = merged.unwrap();
+        assert_eq!(headers.len(), 2);
+        assert!(film.pixels.iter().enumerate().all(|(i, pixel)| pixel.count == 2 * i as u32));
+        let error = mismatched.err().expect("checkpoints with different path limits were merged");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("it was rendered with different path depth limits"), "{}", error);
+        assert_eq!(header().mismatch(&CheckpointHeader { width: 4, ..header() }), Some("is 3x2, not 4x2".to_string()));
+        assert_eq!(header().mismatch(&CheckpointHeader { spectral: false, ..header() }), Some("was rendered with --spectral".to_string()));
+    }
+}
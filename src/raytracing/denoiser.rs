@@ -0,0 +1,168 @@
+use cgmath::*;
+use raytracing::Film;
+use raytracing::aov::Aov;
+use raytracing::util::maths::luminance;
+use std::f32;
+
+// B3 spline weights of the 5x5 kernel, spread further apart each pass
+const KERNEL: [f32; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+// Stands in for the variance of pixels with too few samples to estimate it, so they're smoothed
+// with anything the other features allow
+const UNKNOWN_VARIANCE: f32 = 1e4;
+// Albedo is divided out before filtering, but not by less than this
+const MIN_ALBEDO: f32 = 0.01;
+const EPSILON: f32 = 1e-6;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010, with the variance guided luminance
+// weights of Schied et al.'s SVGF). Each pass blurs with a 5x5 kernel over pixels twice as far
+// apart as the last, but only between pixels that look like the same surface: similar normals and
+// depth, and colours within what their noise can explain. The albedo is divided out first so
+// textures survive, and any light seen directly is set aside so it isn't spread onto what's
+// around it.
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    // How many standard errors apart luminances can be and still be blended
+    pub luminance_sigma: f32,
+    // Exponent on the cosine between normals
+    pub normal_sigma: f32,
+    // How many times the local depth gradient depths can differ by
+    pub depth_sigma: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            luminance_sigma: 4.,
+            normal_sigma: 128.,
+            depth_sigma: 1.,
+        }
+    }
+}
+
+impl Denoiser {
+    // Uses the film's albedo, emission, normal and depth AOVs if it has them, otherwise the filter
+    // is only guided by the colours and their variance.
+    pub fn denoise(&self, film: &Film) -> Vec<f32> {
+        let (width, height) = (film.width, film.height);
+        let aov = |aov: Aov| film.aovs.iter().find(|buffer| buffer.aov == aov).map(|buffer| &buffer.values);
+        let albedo: Vec<Vector3<f32>> = match aov(Aov::Albedo) {
+            Some(values) => values.chunks(3).map(|a| vec3(a[0].max(MIN_ALBEDO), a[1].max(MIN_ALBEDO), a[2].max(MIN_ALBEDO))).collect(),
+            None => vec![vec3(1., 1., 1.); width * height],
+        };
+        let normals: Option<Vec<Vector3<f32>>> = aov(Aov::Normal).map(|values| {
+            values.chunks(3).map(|n| {
+                let n = vec3(n[0], n[1], n[2]);
+                if n.magnitude2() > 0. { n.normalize() } else { n }
+            }).collect()
+        });
+        let emission: Vec<Vector3<f32>> = match aov(Aov::Emission) {
+            Some(values) => values.chunks(3).map(|e| vec3(e[0], e[1], e[2])).collect(),
+            None => vec![Vector3::zero(); width * height],
+        };
+        let depths = aov(Aov::Depth);
+        let depth_gradients = depths.map(|depths| depth_gradients(depths, width, height));
+
+        let mut colours: Vec<Vector3<f32>> = film.accumulated_image.chunks(3).zip(albedo.iter()).zip(emission.iter())
+            .map(|((c, a), e)| (vec3(c[0], c[1], c[2]) - e).div_element_wise(*a))
+            .collect();
+        let mut variances: Vec<f32> = film.pixels.iter().zip(albedo.iter())
+            .map(|(stats, a)| (stats.mean_variance() / luminance(*a).powi(2)).min(UNKNOWN_VARIANCE))
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1isize << iteration;
+            let blurred_variances = blur_3x3(&variances, width, height);
+            let mut filtered_colours = Vec::with_capacity(colours.len());
+            let mut filtered_variances = Vec::with_capacity(variances.len());
+            for y in 0..height {
+                for x in 0..width {
+                    let p = y * width + x;
+                    let luminance_p = luminance(colours[p]);
+                    let luminance_scale = self.luminance_sigma * blurred_variances[p].sqrt() + EPSILON;
+                    let (mut colour_sum, mut variance_sum, mut weight_sum) = (Vector3::zero(), 0., 0.);
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as isize + (j as isize - 2) * step;
+                        if qy < 0 || qy >= height as isize {
+                            continue;
+                        }
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (i as isize - 2) * step;
+                            if qx < 0 || qx >= width as isize {
+                                continue;
+                            }
+                            let q = qy as usize * width + qx as usize;
+                            let mut weight = kx * ky * (-(luminance_p - luminance(colours[q])).abs() / luminance_scale).exp();
+                            if let Some(ref normals) = normals {
+                                weight *= dot(normals[p], normals[q]).max(0.).powf(self.normal_sigma);
+                            }
+                            if let (Some(depths), Some(ref gradients)) = (depths, &depth_gradients) {
+                                let distance = (((i as isize - 2) * step).pow(2) + ((j as isize - 2) * step).pow(2)) as f32;
+                                let scale = self.depth_sigma * gradients[p] * distance.sqrt() + EPSILON;
+                                weight *= (-(depths[p] - depths[q]).abs() / scale).exp();
+                            }
+                            colour_sum += colours[q] * weight;
+                            variance_sum += variances[q] * weight * weight;
+                            weight_sum += weight;
+                        }
+                    }
+                    // The pixel itself always has a weight, unless it has a normal of zero
+                    if weight_sum > 0. {
+                        filtered_colours.push(colour_sum / weight_sum);
+                        filtered_variances.push(variance_sum / (weight_sum * weight_sum));
+                    } else {
+                        filtered_colours.push(colours[p]);
+                        filtered_variances.push(variances[p]);
+                    }
+                }
+            }
+            colours = filtered_colours;
+            variances = filtered_variances;
+        }
+
+        colours.iter().zip(albedo.iter()).zip(emission.iter()).flat_map(|((c, a), e)| {
+            let c = c.mul_element_wise(*a) + e;
+            vec![c.x, c.y, c.z]
+        }).collect()
+    }
+}
+
+// How quickly the depth changes from pixel to pixel, so that depths are compared relative to it
+// across a surface seen at an angle. Along each axis it takes the smaller difference to the
+// neighbours either side, as at an edge one of them is on another surface.
+fn depth_gradients(depths: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut gradients = Vec::with_capacity(depths.len());
+    for y in 0..height {
+        for x in 0..width {
+            let depth = depths[y * width + x];
+            // Infinite where there's no neighbour, as at the image's edges
+            let difference = |qx: usize, qy: usize, exists: bool| if exists { (depths[qy * width + qx] - depth).abs() } else { f32::INFINITY };
+            let dx = difference(x.wrapping_sub(1), y, x > 0).min(difference(x + 1, y, x + 1 < width));
+            let dy = difference(x, y.wrapping_sub(1), y > 0).min(difference(x, y + 1, y + 1 < height));
+            gradients.push([dx, dy].iter().cloned().filter(|d| d.is_finite()).fold(0., f32::max));
+        }
+    }
+    gradients
+}
+
+fn blur_3x3(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut blurred = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (mut sum, mut weight_sum) = (0., 0.);
+            for dy in -1..=1isize {
+                for dx in -1..=1isize {
+                    let (qx, qy) = (x as isize + dx, y as isize + dy);
+                    if qx >= 0 && qy >= 0 && (qx as usize) < width && (qy as usize) < height {
+                        let weight = KERNEL[(dx + 2) as usize] * KERNEL[(dy + 2) as usize];
+                        sum += values[qy as usize * width + qx as usize] * weight;
+                        weight_sum += weight;
+                    }
+                }
+            }
+            blurred.push(sum / weight_sum);
+        }
+    }
+    blurred
+}
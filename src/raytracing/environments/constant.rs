@@ -0,0 +1,12 @@
+use cgmath::*;
+use raytracing::Environment;
+
+pub struct ConstantEnvironment {
+    pub colour: Vector3<f32>,
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _direction: &Vector3<f32>) -> Vector3<f32> {
+        self.colour
+    }
+}
@@ -0,0 +1,15 @@
+use cgmath::*;
+use raytracing::Environment;
+
+// A simple sky, blending from `bottom` straight down to `top` straight up.
+pub struct GradientEnvironment {
+    pub bottom: Vector3<f32>,
+    pub top: Vector3<f32>,
+}
+
+impl Environment for GradientEnvironment {
+    fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let t = 0.5 * (direction.y + 1.);
+        self.bottom * (1. - t) + self.top * t
+    }
+}
@@ -0,0 +1,111 @@
+use cgmath::*;
+use exr::prelude::read_first_rgba_layer_from_file;
+use image::hdr::HDRDecoder;
+use raytracing::Environment;
+use raytracing::samplers::Sampler;
+use raytracing::util::distribution::Distribution2D;
+use raytracing::util::maths::luminance;
+use std::f32;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+// An equirectangular (latitude-longitude) map of the light from every direction, with +Y up. The
+// middle of the image is seen looking down -Z, and the map can be turned about the Y axis.
+// Directions are sampled in proportion to the brightness of the pixels, so that a small, bright
+// sun in the map lights the scene without noise.
+pub struct ImageEnvironment {
+    width: usize,
+    height: usize,
+    // Linear RGB, scaled by the intensity
+    pixels: Vec<Vector3<f32>>,
+    distribution: Distribution2D,
+    rotation: Basis3<f32>,
+}
+
+impl ImageEnvironment {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector3<f32>>, rotation: Deg<f32>, intensity: f32) -> ImageEnvironment {
+        let pixels: Vec<Vector3<f32>> = pixels.iter().map(|p| p * intensity).collect();
+        // Rows nearer the poles cover less of the sphere, so are less likely to be picked
+        let weights: Vec<f32> = pixels.iter().enumerate().map(|(i, p)| {
+            let theta = ((i / width) as f32 + 0.5) / height as f32 * f32::consts::PI;
+            luminance(*p) * theta.sin()
+        }).collect();
+        let distribution = Distribution2D::new(&weights, width, height);
+        ImageEnvironment { width, height, pixels, distribution, rotation: Basis3::from_angle_y(rotation) }
+    }
+
+    // Loads a Radiance HDR (.hdr) or OpenEXR (.exr) image, which are already linear.
+    pub fn load<P: AsRef<Path>>(path: P, rotation: Deg<f32>, intensity: f32) -> io::Result<ImageEnvironment> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase());
+        let (width, height, pixels) = match extension.as_deref() {
+            Some("hdr") => {
+                let decoder = HDRDecoder::new(BufReader::new(File::open(path)?)).map_err(io::Error::other)?;
+                let metadata = decoder.metadata();
+                let pixels = decoder.read_image_hdr().map_err(io::Error::other)?;
+                let pixels = pixels.iter().map(|p| vec3(p.data[0], p.data[1], p.data[2])).collect();
+                (metadata.width as usize, metadata.height as usize, pixels)
+            },
+            Some("exr") => {
+                let image = read_first_rgba_layer_from_file(
+                    path,
+                    |resolution, _| (resolution.width(), vec![Vector3::zero(); resolution.width() * resolution.height()]),
+                    |&mut (width, ref mut pixels): &mut (usize, Vec<Vector3<f32>>), position, (r, g, b, _): (f32, f32, f32, f32)| {
+                        pixels[position.y() * width + position.x()] = vec3(r, g, b);
+                    },
+                ).map_err(io::Error::other)?;
+                let (width, pixels) = image.layer_data.channel_data.pixels;
+                (width, pixels.len() / width.max(1), pixels)
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "environment maps must be .hdr or .exr images")),
+        };
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty image"));
+        }
+        Ok(ImageEnvironment::new(width, height, pixels, rotation, intensity))
+    }
+
+    // Direction of the point (u, v) in the image, before rotating, with v running down from +Y.
+    fn direction(u: f32, v: f32) -> Vector3<f32> {
+        let phi = (u - 0.5) * 2. * f32::consts::PI;
+        let theta = v * f32::consts::PI;
+        vec3(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    fn image_point(direction: &Vector3<f32>) -> Point2<f32> {
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2. * f32::consts::PI);
+        let v = direction.y.clamp(-1., 1.).acos() / f32::consts::PI;
+        Point2::new(u - u.floor(), v)
+    }
+}
+
+impl Environment for ImageEnvironment {
+    fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let p = ImageEnvironment::image_point(&self.rotation.invert().rotate_vector(*direction));
+        let i = ((p.x * self.width as f32) as usize).min(self.width - 1);
+        let j = ((p.y * self.height as f32) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+
+    // The pdf over the image is converted to one over solid angle, which each pixel covers
+    // 2π²sin(θ) times less of.
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vector3<f32>, f32)> {
+        let (p, pdf) = self.distribution.sample(sampler.get_2d());
+        let sin_theta = (p.y * f32::consts::PI).sin();
+        if pdf <= 0. || sin_theta <= 0. {
+            return None;
+        }
+        let direction = self.rotation.rotate_vector(ImageEnvironment::direction(p.x, p.y));
+        Some((direction, pdf / (2. * f32::consts::PI * f32::consts::PI * sin_theta)))
+    }
+
+    fn pdf(&self, direction: &Vector3<f32>) -> f32 {
+        let p = ImageEnvironment::image_point(&self.rotation.invert().rotate_vector(*direction));
+        let sin_theta = (p.y * f32::consts::PI).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        self.distribution.pdf(p) / (2. * f32::consts::PI * f32::consts::PI * sin_theta)
+    }
+}
@@ -0,0 +1,11 @@
+mod constant;
+pub use self::constant::ConstantEnvironment;
+
+mod gradient;
+pub use self::gradient::GradientEnvironment;
+
+mod image_environment;
+pub use self::image_environment::ImageEnvironment;
+
+mod sky;
+pub use self::sky::SkyEnvironment;
@@ -0,0 +1,177 @@
+use cgmath::*;
+use raytracing::Environment;
+use raytracing::samplers::Sampler;
+use raytracing::util::maths;
+use std::f32;
+
+// Angular radius of the sun's disk
+const SUN_RADIUS: Deg<f32> = Deg(0.2667);
+// Luminance of the sun above the atmosphere, in the sky's units of kcd/m²
+const SUN_LUMINANCE: f32 = 1.88e6;
+// Wavelengths in µm that the red, green and blue of the sun are attenuated at
+const WAVELENGTHS: [f32; 3] = [0.65, 0.55, 0.45];
+// Chance of `sample` picking a direction on the sun rather than the sky
+const SUN_SAMPLE_PROBABILITY: f32 = 0.5;
+
+// Perez et al.'s all-weather model of how the sky's brightness varies with the angle from the
+// zenith (theta) and from the sun (gamma).
+struct Perez([f32; 5]);
+
+impl Perez {
+    fn value(&self, cos_theta: f32, gamma: f32, cos_gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        (1. + a * (b / cos_theta).exp()) * (1. + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+// A clear daylight sky (Preetham, Shirley and Smits' analytic model) with the sun in it, for a sun
+// `elevation` degrees above the horizon and at `azimuth` degrees clockwise from -Z when seen from
+// above. Turbidity is the haziness of the air, from about 2 for a clear day to 10 for a hazy one,
+// which also reddens and dims the sun. Below the horizon it's black, like unlit ground.
+//
+// Radiance comes out in kcd/m², where a sunlit surface is tens of times brighter than 1, so the
+// intensity (or the exposure) needs turning down.
+pub struct SkyEnvironment {
+    sun_direction: Vector3<f32>,
+    sun_radiance: Vector3<f32>,
+    cos_sun_radius: f32,
+    // Luminance Y and chromaticity x, y at the zenith, each divided by the Perez function there
+    zenith: [f32; 3],
+    perez: [Perez; 3],
+    intensity: f32,
+}
+
+impl SkyEnvironment {
+    pub fn new(elevation: Deg<f32>, azimuth: Deg<f32>, turbidity: f32, intensity: f32) -> SkyEnvironment {
+        let t = turbidity;
+        let (sin_elevation, cos_elevation) = Rad::from(elevation).sin_cos();
+        let (sin_azimuth, cos_azimuth) = Rad::from(azimuth).sin_cos();
+        let sun_direction = vec3(cos_elevation * sin_azimuth, sin_elevation, -cos_elevation * cos_azimuth);
+        let theta_s = f32::consts::FRAC_PI_2 - Rad::from(elevation).0;
+
+        let chi = (4. / 9. - t / 120.) * (f32::consts::PI - 2. * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.];
+        let chromaticity = |m: [[f32; 4]; 3]| {
+            let row = |r: [f32; 4]| r.iter().zip(thetas.iter()).map(|(a, b)| a * b).sum::<f32>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let perez = [
+            Perez([0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703]),
+            Perez([-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452]),
+            Perez([-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]),
+        ];
+        let cos_theta_s = theta_s.cos();
+        let zenith = [
+            zenith_luminance / perez[0].value(1., theta_s, cos_theta_s),
+            zenith_x / perez[1].value(1., theta_s, cos_theta_s),
+            zenith_y / perez[2].value(1., theta_s, cos_theta_s),
+        ];
+
+        SkyEnvironment {
+            sun_direction,
+            sun_radiance: sun_radiance(theta_s, turbidity),
+            cos_sun_radius: Rad::from(SUN_RADIUS).cos(),
+            zenith,
+            perez,
+            intensity,
+        }
+    }
+
+    fn sky_radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let cos_theta = direction.y.max(0.001);
+        let cos_gamma = dot(*direction, self.sun_direction).clamp(-1., 1.);
+        let gamma = cos_gamma.acos();
+        let luminance = self.zenith[0] * self.perez[0].value(cos_theta, gamma, cos_gamma);
+        let x = self.zenith[1] * self.perez[1].value(cos_theta, gamma, cos_gamma);
+        let y = self.zenith[2] * self.perez[2].value(cos_theta, gamma, cos_gamma);
+        xyy_to_rgb(x, y, luminance.max(0.))
+    }
+
+    fn sun_pdf(&self) -> f32 {
+        1. / (2. * f32::consts::PI * (1. - self.cos_sun_radius))
+    }
+}
+
+// The sun's colour after passing through the air (Preetham et al.'s Rayleigh and aerosol
+// transmittance, leaving out absorption by ozone and water vapour).
+fn sun_radiance(theta_s: f32, turbidity: f32) -> Vector3<f32> {
+    // Relative optical mass, the length of the path through the air compared to straight up
+    let mass = 1. / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |wavelength: f32| {
+        let rayleigh = (-0.008735 * wavelength.powf(-4.08) * mass).exp();
+        let aerosol = (-beta * wavelength.powf(-1.3) * mass).exp();
+        rayleigh * aerosol
+    };
+    vec3(transmittance(WAVELENGTHS[0]), transmittance(WAVELENGTHS[1]), transmittance(WAVELENGTHS[2])) * SUN_LUMINANCE
+}
+
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vector3<f32> {
+    if y <= 0. {
+        return Vector3::zero();
+    }
+    let (cx, cy, cz) = (x / y * luminance, luminance, (1. - x - y) / y * luminance);
+    vec3(
+        (3.2406 * cx - 1.5372 * cy - 0.4986 * cz).max(0.),
+        (-0.9689 * cx + 1.8758 * cy + 0.0415 * cz).max(0.),
+        (0.0557 * cx - 0.2040 * cy + 1.0570 * cz).max(0.),
+    )
+}
+
+impl Environment for SkyEnvironment {
+    fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        if direction.y <= 0. {
+            return Vector3::zero();
+        }
+        let mut radiance = self.sky_radiance(direction);
+        if dot(*direction, self.sun_direction) >= self.cos_sun_radius {
+            radiance += self.sun_radiance;
+        }
+        radiance * self.intensity
+    }
+
+    // Either a direction on the sun's disk or one uniformly over the sky.
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vector3<f32>, f32)> {
+        let pick = sampler.get_1d();
+        let u = sampler.get_2d();
+        let direction = if pick < SUN_SAMPLE_PROBABILITY {
+            let cos_theta = 1. - u.x * (1. - self.cos_sun_radius);
+            let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+            let phi = 2. * f32::consts::PI * u.y;
+            let (tangent, bitangent) = maths::orthonormal_basis(self.sun_direction);
+            tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + self.sun_direction * cos_theta
+        } else {
+            let cos_theta = u.x;
+            let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+            let phi = 2. * f32::consts::PI * u.y;
+            vec3(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+        };
+        let pdf = self.pdf(&direction);
+        if pdf <= 0. {
+            return None;
+        }
+        Some((direction, pdf))
+    }
+
+    fn pdf(&self, direction: &Vector3<f32>) -> f32 {
+        if direction.y <= 0. {
+            return 0.;
+        }
+        let mut pdf = (1. - SUN_SAMPLE_PROBABILITY) / (2. * f32::consts::PI);
+        if dot(*direction, self.sun_direction) >= self.cos_sun_radius {
+            pdf += SUN_SAMPLE_PROBABILITY * self.sun_pdf();
+        }
+        pdf
+    }
+}
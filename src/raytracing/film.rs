@@ -0,0 +1,135 @@
+use cgmath::*;
+use raytracing::Tile;
+use raytracing::aov::{Aov, AovBuffer};
+use raytracing::util::maths::luminance;
+
+// Below this luminance the error is taken relative to it instead, so black pixels can converge
+const MIN_RELATIVE_LUMINANCE: f32 = 0.001;
+
+// Running statistics of a pixel's samples with Welford's algorithm: the mean colour and, for the
+// variance, the sum of squared differences of the luminance from its mean.
+#[derive(Clone, Copy, Debug)]
+pub struct PixelStats {
+    pub mean: Vector3<f32>,
+    pub m2: f32,
+    pub count: u32,
+}
+
+impl PixelStats {
+    pub fn new() -> PixelStats {
+        PixelStats { mean: Vector3::zero(), m2: 0., count: 0 }
+    }
+
+    pub fn add(&mut self, colour: Vector3<f32>) {
+        self.count += 1;
+        let delta = luminance(colour) - luminance(self.mean);
+        self.mean += (colour - self.mean) / self.count as f32;
+        self.m2 += delta * (luminance(colour) - luminance(self.mean));
+    }
+
+    // Combines the statistics of two sets of samples (Chan et al's parallel form of Welford's).
+    pub fn merge(&mut self, other: &PixelStats) {
+        if other.count == 0 {
+            return;
+        }
+        let (n_a, n_b) = (self.count as f32, other.count as f32);
+        let n = n_a + n_b;
+        let delta = luminance(other.mean) - luminance(self.mean);
+        self.mean = (self.mean * n_a + other.mean * n_b) / n;
+        self.m2 += other.m2 + delta * delta * n_a * n_b / n;
+        self.count += other.count;
+    }
+
+    // Variance of the mean luminance (the squared standard error), infinite until there are enough
+    // samples to estimate it.
+    pub fn mean_variance(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.count as f32;
+        self.m2 / (n - 1.) / n
+    }
+
+    // Standard error of the mean luminance relative to the mean.
+    pub fn relative_error(&self) -> f32 {
+        self.mean_variance().sqrt() / luminance(self.mean).max(MIN_RELATIVE_LUMINANCE)
+    }
+}
+
+impl Default for PixelStats {
+    fn default() -> PixelStats {
+        PixelStats::new()
+    }
+}
+
+// Everything accumulated for an image so far: the average of each pixel's samples (RGB, f32 per
+// channel, as written out), alongside the statistics to tell how noisy it still is, and the
+// average of any AOVs.
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub accumulated_image: Vec<f32>,
+    pub pixels: Vec<PixelStats>,
+    pub aovs: Vec<AovBuffer>,
+    // Samples the AOVs are averaged over, which after resuming a render are only the new ones
+    aov_counts: Vec<u32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film::from_pixels(width, height, vec![PixelStats::new(); width * height])
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<PixelStats>) -> Film {
+        let accumulated_image = pixels.iter().flat_map(|p| vec![p.mean.x, p.mean.y, p.mean.z]).collect();
+        Film { width, height, accumulated_image, pixels, aovs: Vec::new(), aov_counts: Vec::new() }
+    }
+
+    pub fn add_aovs(&mut self, aovs: &[Aov]) {
+        let pixel_count = self.width * self.height;
+        self.aovs.extend(aovs.iter().map(|&aov| AovBuffer::new(aov, pixel_count)));
+        self.aov_counts = vec![0; pixel_count];
+    }
+
+    // Folds in new samples for a tile's pixels, given row by row, with the sums of their AOVs.
+    pub fn merge_tile(&mut self, tile: &Tile, stats: &[PixelStats], aovs: &[AovBuffer]) {
+        for (i, tile_stats) in stats.iter().enumerate() {
+            let pixel = (tile.y + i / tile.width) * self.width + tile.x + i % tile.width;
+            self.pixels[pixel].merge(tile_stats);
+            let mean = self.pixels[pixel].mean;
+            self.accumulated_image[(pixel * 3)..(pixel * 3 + 3)].copy_from_slice(&[mean.x, mean.y, mean.z]);
+
+            if tile_stats.count == 0 || aovs.is_empty() {
+                continue;
+            }
+            let (count, new_count) = (self.aov_counts[pixel], tile_stats.count);
+            for (buffer, tile_buffer) in self.aovs.iter_mut().zip(aovs.iter()) {
+                let channels = buffer.aov.channels().len();
+                let averaged = buffer.aov.is_averaged();
+                let values = &mut buffer.values[(pixel * channels)..((pixel + 1) * channels)];
+                for (value, &sum) in values.iter_mut().zip(tile_buffer.pixel(i)) {
+                    if averaged {
+                        *value = (*value * count as f32 + sum) / (count + new_count) as f32;
+                    } else if count == 0 {
+                        *value = sum;
+                    }
+                }
+            }
+            self.aov_counts[pixel] += new_count;
+        }
+    }
+
+    // Combines the samples of another film of the same image, weighting each pixel by its counts.
+    pub fn merge(&mut self, other: &Film) {
+        for (pixel, other_stats) in self.pixels.iter_mut().zip(other.pixels.iter()) {
+            pixel.merge(other_stats);
+        }
+        for (pixel, stats) in self.pixels.iter().enumerate() {
+            self.accumulated_image[(pixel * 3)..(pixel * 3 + 3)].copy_from_slice(&[stats.mean.x, stats.mean.y, stats.mean.z]);
+        }
+    }
+
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(|p| p.count).collect()
+    }
+}
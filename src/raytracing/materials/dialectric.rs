@@ -1,40 +1,94 @@
 use cgmath::*;
-use rand::{random};
 use raytracing::{Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
+use raytracing::samplers::Sampler;
 use raytracing::util::{maths};
 
+// Wavelength (in nm) of the sodium D line, which refractive indices are usually quoted at, and
+// which is used when rendering in RGB
+const D_LINE: f32 = 587.6;
+
+// How the refractive index changes with wavelength, with Cauchy's and Sellmeier's coefficients for
+// wavelengths in µm.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RefractiveIndex {
+    Constant(f32),
+    // n = a + b/λ² + c/λ⁴
+    Cauchy { a: f32, b: f32, c: f32 },
+    // n² = 1 + Σ bλ²/(λ² - c), for each (b, c)
+    Sellmeier(Vec<(f32, f32)>),
+}
+
+impl RefractiveIndex {
+    // Some of the materials in refractiveindex.info, by name.
+    pub fn named(name: &str) -> Option<RefractiveIndex> {
+        let terms = match name {
+            // Schott N-BK7, the common optical glass
+            "bk7" => vec![(1.039612, 0.006000699), (0.2317923, 0.02001791), (1.010469, 103.5607)],
+            // Malitson 1965
+            "fused_silica" => vec![(0.6961663, 0.004679148), (0.4079426, 0.01351206), (0.8974794, 97.934)],
+            // Peter 1923
+            "diamond" => vec![(0.3306, 0.030625), (4.3356, 0.011236)],
+            // Daimon and Masumura 2007, at 20°C
+            "water" => vec![(0.5684028, 0.00510183), (0.1726177, 0.01821154), (0.0208619, 0.02620722), (0.1130749, 10.69793)],
+            _ => return None,
+        };
+        Some(RefractiveIndex::Sellmeier(terms))
+    }
+
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let micrometres = wavelength / 1000.;
+        let squared = micrometres * micrometres;
+        match *self {
+            RefractiveIndex::Constant(index) => index,
+            RefractiveIndex::Cauchy { a, b, c } => a + b / squared + c / (squared * squared),
+            RefractiveIndex::Sellmeier(ref terms) => {
+                (1. + terms.iter().map(|&(b, c)| b * squared / (squared - c)).sum::<f32>()).sqrt()
+            },
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(*self, RefractiveIndex::Constant(_))
+    }
+}
+
+// Glass, water and the like. A dispersive index splits white light into its colours, but only
+// when rendering spectrally, where rays carry a wavelength; in RGB the index at the D line is used.
 pub struct Dialectric {
-    pub refractive_index: f32,
+    pub refractive_index: RefractiveIndex,
 }
 
 impl Scattering for Dialectric {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<ScatteredRay> {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatteredRay> {
         let reflected = maths::reflect(ray.direction, hit.normal);
         let attenuation = vec3(1., 1., 1.);
+        let refractive_index = self.refractive_index.at(ray.wavelength.unwrap_or(D_LINE));
+        // Which way the ray goes depends on its wavelength, so it can't carry any others on
+        let dispersed = ray.wavelength.is_some() && self.refractive_index.is_dispersive();
 
         let outward_normal;
         let ni_over_nt;
         let cosine = if dot(ray.direction, hit.normal) > 0. {
             outward_normal = -hit.normal;
-            ni_over_nt = self.refractive_index;
-            self.refractive_index * dot(ray.direction, hit.normal) / ray.direction.magnitude()
+            ni_over_nt = refractive_index;
+            refractive_index * dot(ray.direction, hit.normal) / ray.direction.magnitude()
         } else {
             outward_normal = hit.normal;
             // :TODO: This assumes air (idx=1) to medium, will need to ensure correct index is used if light is crossing boundary between two mediums
-            ni_over_nt = 1. / self.refractive_index;
+            ni_over_nt = 1. / refractive_index;
             -dot(ray.direction, hit.normal) / ray.direction.magnitude()
         };
 
         let refracted = maths::refract(ray.direction, outward_normal, ni_over_nt);
         let reflect_probability = match refracted {
             None => 1.0,
-            Some(_refracted) => maths::schlick(cosine, self.refractive_index),
+            Some(_refracted) => maths::schlick(cosine, refractive_index),
         };
 
-        let scattered = if random::<f32>() < reflect_probability {
-            ScatteredRay { ray: Ray { origin: hit.location, direction: reflected }, attenuation }
+        let scattered = if sampler.get_1d() < reflect_probability {
+            ScatteredRay { ray: Ray { origin: hit.location, direction: reflected, wavelength: ray.wavelength }, attenuation, pdf: 0., specular: true, dispersed }
         } else {
-            ScatteredRay { ray: Ray { origin: hit.location, direction: refracted.unwrap() }, attenuation }
+            ScatteredRay { ray: Ray { origin: hit.location, direction: refracted.unwrap(), wavelength: ray.wavelength }, attenuation, pdf: 0., specular: true, dispersed }
         };
 
         Some(scattered)
@@ -42,4 +96,4 @@ impl Scattering for Dialectric {
 }
 
 impl Emitting for Dialectric {}
-impl ScatteringAndEmitting for Dialectric {}
\ No newline at end of file
+impl ScatteringAndEmitting for Dialectric {}
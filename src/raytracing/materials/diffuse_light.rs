@@ -1,19 +1,23 @@
 use cgmath::*;
-use raytracing::{Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
+use raytracing::{BoxedTexture, Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
+use raytracing::samplers::Sampler;
 
 pub struct DiffuseLight {
-	pub colour: Vector3<f32>,
+	pub colour: BoxedTexture,
 }
 
 impl Emitting for DiffuseLight {
-	fn emit(&self, _u: f32, _v: f32, _p: &Point3<f32>) -> Vector3<f32> {
-		// :TODO:
-		self.colour
+	fn is_emitting(&self) -> bool {
+		true
+	}
+
+	fn emit(&self, u: f32, v: f32, p: &Point3<f32>) -> Vector3<f32> {
+		self.colour.value(u, v, p)
 	}
 }
 
 impl Scattering for DiffuseLight {
-    fn scatter(&self, _ray: &Ray, _hit: &Hit) -> Option<ScatteredRay> {
+    fn scatter(&self, _ray: &Ray, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<ScatteredRay> {
         None
     }
 }
@@ -1,17 +1,33 @@
 use cgmath::*;
-use raytracing::{Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
-use raytracing::util::{random};
+use raytracing::{BoxedTexture, Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
+use raytracing::samplers::Sampler;
+use raytracing::util::random;
+use std::f32;
 
 pub struct Lambertian {
-    pub albedo: Vector3<f32>,
+    pub albedo: BoxedTexture,
 }
 
 impl Scattering for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit: &Hit) -> Option<ScatteredRay> {
-        let target = hit.location + hit.normal + random::random_unit_vector();
-        let scattered_ray = Ray { origin: hit.location, direction: (target - hit.location).normalize() };
-        let attenuation = self.albedo;
-        Some(ScatteredRay { ray: scattered_ray, attenuation })
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatteredRay> {
+        let target = hit.location + hit.normal + random::random_unit_vector(sampler);
+        let scattered_ray = Ray { origin: hit.location, direction: (target - hit.location).normalize(), wavelength: ray.wavelength };
+        let attenuation = self.albedo.value(hit.uv.x, hit.uv.y, &hit.location);
+        let pdf = self.pdf(ray, hit, &scattered_ray.direction);
+        Some(ScatteredRay { ray: scattered_ray, attenuation, pdf, specular: false, dispersed: false })
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &Hit, direction: &Vector3<f32>) -> Vector3<f32> {
+        self.albedo.value(hit.uv.x, hit.uv.y, &hit.location) * self.pdf(ray, hit, direction)
+    }
+
+    // Offsetting the normal by a random unit vector gives a cosine weighted distribution
+    fn pdf(&self, _ray: &Ray, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        dot(*direction, hit.normal).max(0.) / f32::consts::PI
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector3<f32> {
+        self.albedo.value(hit.uv.x, hit.uv.y, &hit.location)
     }
 }
 
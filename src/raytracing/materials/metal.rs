@@ -1,23 +1,66 @@
 use cgmath::*;
-use raytracing::{Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
+use raytracing::{BoxedTexture, Emitting, Hit, Ray, Scattering, ScatteringAndEmitting, ScatteredRay};
+use raytracing::samplers::Sampler;
 use raytracing::util::{maths, random};
+use std::f32;
 
 pub struct Metal {
-    pub albedo: Vector3<f32>,
+    pub albedo: BoxedTexture,
     pub fuzziness: f32,
 }
 
+impl Metal {
+    // Density of the direction towards a point picked uniformly on the sphere of radius `fuzziness`
+    // around the (unit) mirror direction. A direction can pass through the sphere twice, and each
+    // point's area density is converted to solid angle.
+    fn fuzzy_reflection_pdf(&self, reflected: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let fuzziness = self.fuzziness.abs();
+        let b = dot(direction, reflected);
+        let discriminant = b * b - 1. + fuzziness * fuzziness;
+        // Rounding can leave directions sampled on the very edge of the cone just outside it
+        if discriminant <= -1e-6 {
+            return 0.;
+        }
+        let root = discriminant.max(1e-7).sqrt();
+        let area = 4. * f32::consts::PI * fuzziness * fuzziness;
+        [b - root, b + root].iter()
+            .filter(|&&t| t > 0.)
+            .map(|&t| maths::area_to_solid_angle_pdf(1. / area, t, root / fuzziness))
+            .sum()
+    }
+}
+
 impl Scattering for Metal {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<ScatteredRay> {
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatteredRay> {
         let reflected = maths::reflect(ray.direction.normalize(), hit.normal);
-        let scattered_ray = Ray{ origin: hit.location, direction: (reflected + self.fuzziness * random::random_unit_vector()).normalize() };
-        let attenuation = self.albedo;
+        let scattered_ray = Ray{ origin: hit.location, direction: (reflected + self.fuzziness * random::random_unit_vector(sampler)).normalize(), wavelength: ray.wavelength };
+        let attenuation = self.albedo.value(hit.uv.x, hit.uv.y, &hit.location);
         if dot(scattered_ray.direction, hit.normal) > 0.0 {
-            Some(ScatteredRay { ray: scattered_ray, attenuation })
+            let specular = self.fuzziness == 0.;
+            let pdf = if specular { 0. } else { self.fuzzy_reflection_pdf(reflected, scattered_ray.direction) };
+            Some(ScatteredRay { ray: scattered_ray, attenuation, pdf, specular, dispersed: false })
         } else {
             None
         }        
     }
+
+    // Scattering keeps the albedo as its weight, with directions below the surface absorbed, so
+    // this is just the albedo scaled by the pdf.
+    fn evaluate(&self, ray: &Ray, hit: &Hit, direction: &Vector3<f32>) -> Vector3<f32> {
+        self.albedo.value(hit.uv.x, hit.uv.y, &hit.location) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        if self.fuzziness == 0. || dot(*direction, hit.normal) <= 0. {
+            return 0.;
+        }
+        let reflected = maths::reflect(ray.direction.normalize(), hit.normal);
+        self.fuzzy_reflection_pdf(reflected, *direction)
+    }
+
+    fn albedo(&self, hit: &Hit) -> Vector3<f32> {
+        self.albedo.value(hit.uv.x, hit.uv.y, &hit.location)
+    }
 }
 
 impl Emitting for Metal {}
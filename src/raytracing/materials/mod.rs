@@ -1,5 +1,5 @@
 mod dialectric;
-pub use self::dialectric::Dialectric;
+pub use self::dialectric::{Dialectric, RefractiveIndex};
 
 mod diffuse_light;
 pub use self::diffuse_light::DiffuseLight;
@@ -1,5 +1,15 @@
+pub mod acceleration;
+pub mod aov;
+pub mod checkpoint;
+pub mod denoiser;
+pub mod environments;
 pub mod materials;
+pub mod output;
+pub mod samplers;
+pub mod scenes;
 pub mod shapes;
+pub mod spectrum;
+pub mod textures;
 pub mod tracing;
 pub mod util;
 
@@ -7,9 +17,21 @@ pub mod cameras;
 pub use self::cameras::{Camera};
 
 mod types;
-pub use self::types::{Ray, Interval};
+pub use self::types::{Aabb, Ray, Interval};
 pub use self::types::{Hit, Hitable, BoxedHitable, HitableCollection};
+pub use self::types::{ShapeSample};
 pub use self::types::{ScatteredRay, Scattering};
 pub use self::types::{Emitting};
 pub use self::types::{ScatteringAndEmitting};
-pub use self::types::{Material};
\ No newline at end of file
+pub use self::types::{Material};
+pub use self::types::{Texture, BoxedTexture};
+pub use self::types::{Environment, BoxedEnvironment};
+
+mod film;
+pub use self::film::{Film, PixelStats};
+
+mod renderer;
+pub use self::renderer::{Integrator, Renderer, RenderSettings, RenderStats};
+
+mod scheduler;
+pub use self::scheduler::{Tile, TileOrder, TileScheduler};
\ No newline at end of file
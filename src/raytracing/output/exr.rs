@@ -0,0 +1,47 @@
+use exr::prelude::{f16, write_rgb_file, AnyChannel, AnyChannels, FlatSamples, Image, SmallVec, WritableImage};
+use std::io;
+
+fn pixel(pixels: &[f32], width: usize, x: usize, y: usize) -> (f32, f32, f32) {
+    let base = ((y * width) + x) * 3;
+    (pixels[base], pixels[base + 1], pixels[base + 2])
+}
+
+// Writes linear RGB as an OpenEXR file, with 16 bit (half) or 32 bit float channels.
+pub fn write_exr(filename: &str, pixels: &[f32], dimensions: (u32, u32), half: bool) -> Result<(), io::Error> {
+    let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+    let result = if half {
+        write_rgb_file(filename, width, height, |x, y| {
+            let (r, g, b) = pixel(pixels, width, x, y);
+            (f16::from_f32(r), f16::from_f32(g), f16::from_f32(b))
+        })
+    } else {
+        write_rgb_file(filename, width, height, |x, y| pixel(pixels, width, x, y))
+    };
+    result.map_err(io::Error::other)
+}
+
+// Channels to store in an EXR file alongside others, with the values of each pixel interleaved.
+// The channel names are prefixed with the layer's name (e.g. `albedo.R`), unless it's empty as for
+// the image itself.
+pub struct ExrLayer<'a> {
+    pub name: &'a str,
+    pub channels: &'a [&'a str],
+    pub values: &'a [f32],
+}
+
+pub fn write_exr_layers(filename: &str, layers: &[ExrLayer], dimensions: (u32, u32), half: bool) -> Result<(), io::Error> {
+    let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+    let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = layers.iter().flat_map(|layer| {
+        layer.channels.iter().enumerate().map(move |(channel, channel_name)| {
+            let name = if layer.name.is_empty() { channel_name.to_string() } else { format!("{}.{}", layer.name, channel_name) };
+            let values = layer.values.iter().skip(channel).step_by(layer.channels.len()).cloned();
+            let samples = if half {
+                FlatSamples::F16(values.map(f16::from_f32).collect())
+            } else {
+                FlatSamples::F32(values.collect())
+            };
+            AnyChannel::new(name.as_str(), samples)
+        })
+    }).collect();
+    Image::from_channels((width, height), AnyChannels::sort(channels)).write().to_file(filename).map_err(io::Error::other)
+}
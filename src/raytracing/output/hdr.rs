@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Shared exponent encoding: the mantissas of all three channels are scaled by the exponent of the
+// brightest.
+fn rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    let brightest = r.max(g).max(b);
+    if brightest < 1e-32 || !brightest.is_finite() {
+        return [0, 0, 0, 0];
+    }
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest * 2f32.powi(-exponent) >= 1. {
+        exponent += 1;
+    }
+    let scale = 256. * 2f32.powi(-exponent);
+    let channel = |v: f32| (v.max(0.) * scale) as u8;
+    [channel(r), channel(g), channel(b), (exponent + 128).clamp(0, 255) as u8]
+}
+
+// Run length encodes one component of a scanline. Runs of at least four equal bytes are stored as
+// (128 + length, value), anything else as (length, bytes...).
+fn write_component<W: Write>(output: &mut W, data: &[u8]) -> Result<(), io::Error> {
+    const MIN_RUN: usize = 4;
+    const MAX_LENGTH: usize = 127;
+    let mut i = 0;
+    while i < data.len() {
+        // Find the start of the next long enough run
+        let mut run_start = i;
+        let mut run_length = 0;
+        while run_start < data.len() {
+            run_length = 1;
+            while run_start + run_length < data.len() && run_length < MAX_LENGTH && data[run_start + run_length] == data[run_start] {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        // Literal bytes before it
+        while i < run_start {
+            let count = (run_start - i).min(MAX_LENGTH);
+            output.write_all(&[count as u8])?;
+            output.write_all(&data[i..(i + count)])?;
+            i += count;
+        }
+
+        if run_start < data.len() && run_length >= MIN_RUN {
+            output.write_all(&[128 + run_length as u8, data[run_start]])?;
+            i = run_start + run_length;
+        }
+    }
+    Ok(())
+}
+
+// Writes linear RGB as a Radiance RGBE (.hdr) file with run length encoded scanlines.
+pub fn write_hdr(filename: &str, pixels: &[f32], dimensions: (u32, u32)) -> Result<(), io::Error> {
+    let (width, height) = (dimensions.0 as usize, dimensions.1 as usize);
+    let mut output = BufWriter::new(File::create(filename)?);
+    write!(output, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut component = vec![0u8; width];
+    for row in pixels.chunks(width * 3) {
+        for (encoded, p) in scanline.iter_mut().zip(row.chunks(3)) {
+            *encoded = rgbe(p[0], p[1], p[2]);
+        }
+        // The run length encoding can only describe scanlines of 8 to 32767 pixels
+        if !(8..32768).contains(&width) {
+            for encoded in &scanline {
+                output.write_all(encoded)?;
+            }
+            continue;
+        }
+        output.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        for c in 0..4 {
+            for (value, encoded) in component.iter_mut().zip(&scanline) {
+                *value = encoded[c];
+            }
+            write_component(&mut output, &component)?;
+        }
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::hdr::HDRDecoder;
+    use std::fs;
+    use std::io::BufReader;
+
+    #[test]
+    fn run_length_encoding_decodes_to_the_same_pixels() {
+        // Runs and noise longer than the encoding's 127 byte limit, runs too short to encode, noise and black, in scanlines both long enough to be
+        // run length encoded and too short to be
+        for &(width, height) in &[(500, 3), (5, 4)] {
+            let mut pixels = Vec::new();
+            for y in 0..height {
+                for x in 0..width {
+                    let value = match x % 400 {
+                        0..=199 => 0.5,
+                        200..=209 => ((x / 2) % 2) as f32,
+                        210..=369 => (x * 37 % 101) as f32 / (y + 1) as f32,
+                        _ => 0.,
+                    };
+                    pixels.extend_from_slice(&[value, value * 0.25, 1e4 * value]);
+                }
+            }
+
+            let filename = std::env::temp_dir().join(format!("rle_test_{}_{}x{}.hdr", std::process::id(), width, height));
+            let filename = filename.to_str().unwrap();
+            write_hdr(filename, &pixels, (width as u32, height as u32)).unwrap();
+            let decoder = HDRDecoder::new(BufReader::new(File::open(filename).unwrap())).unwrap();
+            let metadata = decoder.metadata();
+            let decoded = decoder.read_image_native().unwrap();
+            fs::remove_file(filename).unwrap();
+
+            assert_eq!((metadata.width, metadata.height), (width as u32, height as u32));
+            let expected: Vec<[u8; 4]> = pixels.chunks(3).map(|p| rgbe(p[0], p[1], p[2])).collect();
+            let decoded: Vec<[u8; 4]> = decoded.iter().map(|p| [p.c[0], p.c[1], p.c[2], p.e]).collect();
+            assert_eq!(decoded, expected, "{}x{} image doesn't decode to the pixels written", width, height);
+        }
+    }
+}
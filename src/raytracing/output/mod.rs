@@ -0,0 +1,52 @@
+mod exr;
+pub use self::exr::{write_exr, write_exr_layers, ExrLayer};
+
+mod hdr;
+pub use self::hdr::{write_hdr};
+
+mod pfm;
+pub use self::pfm::{write_pfm};
+
+mod png;
+pub use self::png::{write_png, write_png_rgb8};
+
+mod tonemap;
+pub use self::tonemap::{srgb_oetf, ToneMapOperator, ToneMapping};
+
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // Display referred, tone mapped to 8 bits per channel
+    Png,
+    // The rest keep the linear radiance as floats
+    Exr { half: bool },
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    // Picks the format from a file's extension, EXR files storing full 32 bit floats.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<OutputFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr { half: false }),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+// Writes an image of linear RGB (f32 per channel) in the given format. Only display referred
+// formats are tone mapped.
+pub fn write_image(filename: &str, format: OutputFormat, pixels: &[f32], dimensions: (u32, u32), tone_mapping: &ToneMapping) -> Result<(), io::Error> {
+    match format {
+        OutputFormat::Png => write_png(filename, pixels, dimensions, tone_mapping),
+        OutputFormat::Exr { half } => write_exr(filename, pixels, dimensions, half),
+        OutputFormat::Hdr => write_hdr(filename, pixels, dimensions),
+        OutputFormat::Pfm => write_pfm(filename, pixels, dimensions),
+    }
+}
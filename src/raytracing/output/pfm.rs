@@ -0,0 +1,17 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Writes linear RGB as a Portable Float Map: a short text header followed by little endian floats,
+// with the rows stored bottom to top.
+pub fn write_pfm(filename: &str, pixels: &[f32], dimensions: (u32, u32)) -> Result<(), io::Error> {
+    let mut output = BufWriter::new(File::create(filename)?);
+    // A negative scale marks the data as little endian
+    write!(output, "PF\n{} {}\n-1.0\n", dimensions.0, dimensions.1)?;
+    let row_length = dimensions.0 as usize * 3;
+    for row in pixels.chunks(row_length).rev() {
+        for value in row {
+            output.write_all(&value.to_le_bytes())?;
+        }
+    }
+    output.flush()
+}
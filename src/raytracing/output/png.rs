@@ -0,0 +1,17 @@
+use image::ColorType;
+use image::png::PNGEncoder;
+use raytracing::output::ToneMapping;
+use std::fs::File;
+use std::io;
+
+pub fn write_png_rgb8(filename: &str, pixels: &[u8], dimensions: (u32, u32)) -> Result<(), io::Error> {
+    let output = File::create(filename)?;
+    let encoder = PNGEncoder::new(output);
+    encoder.encode(pixels, dimensions.0, dimensions.1, ColorType::RGB(8))?;
+    Ok(())
+}
+
+pub fn write_png(filename: &str, pixels: &[f32], dimensions: (u32, u32), tone_mapping: &ToneMapping) -> Result<(), io::Error> {
+    let converted = tone_mapping.image_to_srgb8(pixels);
+    write_png_rgb8(filename, converted.as_slice(), dimensions)
+}
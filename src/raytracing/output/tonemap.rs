@@ -0,0 +1,151 @@
+use cgmath::*;
+use raytracing::util::maths::luminance;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    // Clips anything brighter than white
+    Clamp,
+    // Compresses luminance with L / (1 + L), never quite reaching white
+    Reinhard,
+    // Reinhard, but reaching white at the luminance given by the white point
+    ExtendedReinhard,
+    // Stephen Hill's fit of the ACES reference rendering and sRGB output transforms
+    AcesFilmic,
+    // Log encoding with a sigmoid contrast curve in the AgX working space (Troy Sobotka's AgX, after
+    // the minimal fit by Benjamin Wrensch), desaturating bright colours towards white
+    Agx,
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMapOperator, String> {
+        match s {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "extended-reinhard" => Ok(ToneMapOperator::ExtendedReinhard),
+            "aces" => Ok(ToneMapOperator::AcesFilmic),
+            "agx" => Ok(ToneMapOperator::Agx),
+            _ => Err(format!("unknown tone mapping operator '{}' (expected clamp, reinhard, extended-reinhard, aces or agx)", s)),
+        }
+    }
+}
+
+// Maps linear scene radiance onto the display: scaled by the exposure, compressed by the operator
+// and finally encoded for an sRGB display.
+#[derive(Clone, Copy, Debug)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // In stops, so each +1 doubles the brightness
+    pub exposure: f32,
+    // Luminance mapped to white by the extended Reinhard operator
+    pub white_point: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> ToneMapping {
+        ToneMapping {
+            operator: ToneMapOperator::AcesFilmic,
+            exposure: 0.,
+            white_point: 4.,
+        }
+    }
+}
+
+fn scale_luminance(c: Vector3<f32>, mapped: f32) -> Vector3<f32> {
+    let l = luminance(c);
+    if l > 0. { c * (mapped / l) } else { Vector3::zero() }
+}
+
+// cgmath matrices are built from columns, these are written as rows to match the references
+fn from_rows(r0: [f32; 3], r1: [f32; 3], r2: [f32; 3]) -> Matrix3<f32> {
+    Matrix3::new(r0[0], r1[0], r2[0], r0[1], r1[1], r2[1], r0[2], r1[2], r2[2])
+}
+
+fn aces_filmic(c: Vector3<f32>) -> Vector3<f32> {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    let input = from_rows(
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    );
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    let output = from_rows(
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    );
+    let rrt_and_odt_fit = |v: f32| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.432951) + 0.238081;
+        a / b
+    };
+    output * (input * c).map(rrt_and_odt_fit)
+}
+
+fn agx(c: Vector3<f32>) -> Vector3<f32> {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+    let inset = from_rows(
+        [0.8424791, 0.0784336, 0.07922375],
+        [0.04232824, 0.8784686, 0.07916613],
+        [0.04237565, 0.0784336, 0.879143],
+    );
+    let outset = from_rows(
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.05289685, 1.151903, -0.09896118],
+        [-0.05297164, -0.09804345, 1.151074],
+    );
+    // Polynomial fit of the default AgX contrast curve, applied to the normalised log encoding
+    let contrast = |v: f32| {
+        let x = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    };
+    // The curve's output is display encoded, so undo that for the sRGB encoding that follows
+    (outset * (inset * c).map(contrast)).map(|v| v.max(0.).powf(2.2))
+}
+
+// The exact sRGB transfer function, linear near black and a 2.4 power curve above.
+pub fn srgb_oetf(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+impl ToneMapping {
+    // Linear radiance to linear display values in [0, 1].
+    pub fn map(&self, c: Vector3<f32>) -> Vector3<f32> {
+        let c = c.map(|v| if v.is_finite() { v.max(0.) } else { 0. }) * 2f32.powf(self.exposure);
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => c,
+            ToneMapOperator::Reinhard => {
+                let l = luminance(c);
+                scale_luminance(c, l / (1. + l))
+            },
+            ToneMapOperator::ExtendedReinhard => {
+                let l = luminance(c);
+                let white2 = self.white_point * self.white_point;
+                scale_luminance(c, l * (1. + l / white2) / (1. + l))
+            },
+            ToneMapOperator::AcesFilmic => aces_filmic(c),
+            ToneMapOperator::Agx => agx(c),
+        };
+        mapped.map(|v| v.clamp(0., 1.))
+    }
+
+    pub fn to_srgb8(&self, r: f32, g: f32, b: f32) -> [u8; 3] {
+        let c = self.map(vec3(r, g, b));
+        let encode = |v: f32| (srgb_oetf(v) * 255. + 0.5) as u8;
+        [encode(c.x), encode(c.y), encode(c.z)]
+    }
+
+    // Converts an image of linear RGB (f32 per channel) to 8 bit sRGB.
+    pub fn image_to_srgb8(&self, pixels: &[f32]) -> Vec<u8> {
+        pixels.chunks(3).flat_map(|p| self.to_srgb8(p[0], p[1], p[2]).to_vec()).collect()
+    }
+}
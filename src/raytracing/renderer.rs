@@ -0,0 +1,211 @@
+use cgmath::*;
+use num_cpus;
+use raytracing::{BoxedEnvironment, Camera, Environment, Hitable, HitableCollection, Interval, PixelStats, Ray, Tile, TileOrder};
+use raytracing::acceleration::{Bvh};
+use raytracing::aov::{Aov, AovBuffer, PrimaryHit};
+use raytracing::tracing::{self, Lights, PathLimits};
+use raytracing::samplers::{self, SamplerType};
+use raytracing::spectrum::PathColours;
+use raytracing::util::random;
+use std::f32;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    // Only follows scattered rays, so lights are found by chance
+    BruteForce,
+    // Also samples a light at every non-specular bounce (next-event estimation)
+    LightSampling,
+    // Combines light and material sampling with multiple importance sampling
+    Mis,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Integrator, String> {
+        match s {
+            "brute-force" => Ok(Integrator::BruteForce),
+            "light-sampling" => Ok(Integrator::LightSampling),
+            "mis" => Ok(Integrator::Mis),
+            _ => Err(format!("unknown integrator '{}' (expected brute-force, light-sampling or mis)", s)),
+        }
+    }
+}
+
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub num_samples: u32,
+    pub thread_count: usize,
+    // Tiles are (at most) this many pixels square
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub integrator: Integrator,
+    pub sampler: SamplerType,
+    // Every random number is derived from this, the pixel and the sample index
+    pub seed: u64,
+    // With a threshold, pixels stop being sampled once the standard error of their mean is below
+    // that fraction of it, though not before they have `adaptive_min_samples` samples
+    pub adaptive_threshold: Option<f32>,
+    pub adaptive_min_samples: u32,
+    // AOVs to fill in from the primary rays
+    pub aovs: Vec<Aov>,
+    pub path_limits: PathLimits,
+    // Trace paths carrying a few wavelengths each rather than RGB
+    pub spectral: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            image_width: 640,
+            image_height: 400,
+            num_samples: 1,
+            thread_count: num_cpus::get(),
+            tile_size: 32,
+            tile_order: TileOrder::Hilbert,
+            integrator: Integrator::Mis,
+            sampler: SamplerType::Sobol,
+            seed: 0,
+            adaptive_threshold: None,
+            adaptive_min_samples: 32,
+            aovs: Vec::new(),
+            path_limits: PathLimits::default(),
+            spectral: false,
+        }
+    }
+}
+
+pub struct RenderStats {
+    pub ray_count: u64,
+    pub elapsed_time: f64,
+    // Pixels that were still being sampled
+    pub active_pixels: usize,
+}
+
+pub struct Renderer {
+    pub settings: RenderSettings,
+    pub world: Bvh,
+    pub camera: Camera,
+    pub environment: Option<BoxedEnvironment>,
+    // Indices into the world's bounded shapes of those that emit light and can be sampled
+    lights: Vec<usize>,
+    // Material index AOV values of each of the scene's shapes
+    material_indices: Vec<u32>,
+    view_matrix: Matrix4<f32>,
+    inv_view_projection_matrix: Matrix4<f32>,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings, shapes: HitableCollection, material_indices: Vec<u32>, camera: Camera, environment: Option<BoxedEnvironment>) -> Renderer {
+        let image_aspect = settings.image_width as f32 / settings.image_height as f32;
+        let view_matrix = Matrix4::look_at(camera.eye, camera.target, camera.up);
+        let projection_matrix = perspective(camera.fov, image_aspect, camera.near, camera.far);
+        let view_projection_matrix = projection_matrix * view_matrix;
+        let inv_view_projection_matrix = view_projection_matrix.inverse_transform().unwrap();
+        let world = Bvh::new(shapes);
+        let lights = world.shapes().iter().enumerate().filter(|&(_, shape)| shape.is_light()).map(|(i, _)| i).collect();
+        Renderer { settings, world, camera, environment, lights, material_indices, view_matrix, inv_view_projection_matrix }
+    }
+
+    pub fn lights(&self) -> Lights<'_> {
+        Lights {
+            shapes: self.lights.iter().map(|&i| (self.world.shape_indices()[i], &*self.world.shapes()[i] as &dyn Hitable)).collect(),
+            environment: self.environment.as_ref().map(|environment| &**environment as &dyn Environment),
+        }
+    }
+
+    pub fn num_pixels(&self) -> usize {
+        self.settings.image_width * self.settings.image_height
+    }
+
+    // What the AOVs are taken from where a camera ray first hits the scene.
+    fn primary_hit(&self, ray: &Ray) -> Option<PrimaryHit> {
+        let (object, hit) = self.world.hit_with_index(ray, &Interval { min: 0.001, max: f32::MAX })?;
+        let camera_position = self.view_matrix.transform_point(hit.location);
+        Some(PrimaryHit {
+            albedo: hit.material.albedo(&hit),
+            emission: hit.material.emit(hit.uv.x, hit.uv.y, &hit.location),
+            normal: hit.normal,
+            camera_normal: self.view_matrix.transform_vector(hit.normal),
+            depth: -camera_position.z,
+            position: hit.location,
+            object_index: object as u32 + 1,
+            material_index: self.material_indices.get(object).cloned().unwrap_or(0),
+        })
+    }
+
+    // Takes `num_samples` more samples for each pixel of the tile that's `active`, adding them to
+    // `stats` and summing the AOVs into `aovs` (the tile's rows one after another). The samples
+    // are numbered on from the pixel's count in `sample_counts` so that each pass draws different
+    // random numbers.
+    pub fn render_tile(&self, tile: &Tile, sample_counts: &[u32], active: &[bool], stats: &mut [PixelStats], aovs: &mut [AovBuffer], ray_count: &mut u64) {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height;
+        let num_samples = self.settings.num_samples;
+        let camera = &self.camera;
+        let lights = self.lights();
+        let mut sampler = samplers::create_sampler(self.settings.sampler, self.settings.seed, num_samples);
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let pixel = (y + tile.y) * image_width + x + tile.x;
+                if !active[pixel] {
+                    continue;
+                }
+                let first_sample = sample_counts[pixel] as u64;
+                for s in 0..num_samples {
+                    sampler.start_sample(pixel as u64, first_sample + s as u64);
+                    let jitter = sampler.get_2d();
+                    let sx = ((x + tile.x) as f32) + jitter.x;
+                    let sy = ((y + tile.y) as f32) + jitter.y;
+
+                    let ndc = Point3::new(
+                        (sx / (image_width as f32 / 2.)) - 1.,
+                        (-sy / (image_height as f32 / 2.)) + 1.,
+                        0.
+                    );
+                    let ray_pos = self.inv_view_projection_matrix.transform_point(ndc);
+                    let ray_dir = (ray_pos - camera.eye).normalize();
+
+                    // :TODO: Defocus blur - tidy up, move some logic into camera struct
+                    let lens_radius = camera.aperture / 2.;
+                    let rd = lens_radius * random::random_in_unit_disk(&mut *sampler);
+                    let cam_up = self.view_matrix.transform_vector(vec3(0., 1., 0.));
+                    let cam_right = ray_dir.cross(cam_up);
+                    let ray_offset = (cam_up * rd.x) + (cam_right * rd.y);
+                    let focus_point = ray_pos + (ray_dir * camera.focal_distance);
+                    let ray_pos = ray_pos + ray_offset;
+                    let ray_dir = (focus_point - ray_pos).normalize();
+
+                    let colours = if self.settings.spectral {
+                        sampler.set_dimension(samplers::WAVELENGTH_DIMENSION);
+                        PathColours::sample_wavelengths(sampler.get_1d())
+                    } else {
+                        PathColours::Rgb
+                    };
+                    let ray = Ray {
+                        origin: ray_pos,
+                        direction: ray_dir,
+                        wavelength: colours.hero_wavelength(),
+                    };
+
+                    if !aovs.is_empty() {
+                        let primary_hit = self.primary_hit(&ray);
+                        for buffer in aovs.iter_mut() {
+                            buffer.add(y * tile.width + x, primary_hit.as_ref(), s == 0);
+                        }
+                    }
+
+                    let limits = &self.settings.path_limits;
+                    let colour = match self.settings.integrator {
+                        Integrator::BruteForce => tracing::trace(&self.world, lights.environment, &ray, colours, limits, &mut *sampler, ray_count),
+                        Integrator::LightSampling => tracing::trace_with_light_sampling(&self.world, &lights, &ray, colours, limits, &mut *sampler, ray_count),
+                        Integrator::Mis => tracing::trace_with_mis(&self.world, &lights, &ray, colours, limits, &mut *sampler, ray_count),
+                    };
+                    stats[y * tile.width + x].add(colour);
+                }
+            }
+        }
+    }
+}
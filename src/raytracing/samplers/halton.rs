@@ -0,0 +1,79 @@
+use cgmath::*;
+use raytracing::samplers::{hash, to_unit_float, Sampler, ONE_MINUS_EPSILON};
+use raytracing::util::random::Rng;
+
+// Bases for the first dimensions; beyond these the sequence correlates badly between dimensions,
+// and later bounces contribute little, so plain random numbers are used instead
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+// The Halton sequence, with each dimension in base of the next prime. Every pixel follows the same
+// sequence, but shifted by a random offset (a Cranley-Patterson rotation) per pixel and dimension so
+// neighbouring pixels don't share their errors.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u64,
+    dimension: u32,
+    rng: Rng,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler { seed, pixel: 0, index: 0, dimension: 0, rng: Rng::for_sample(seed, 0, 0) }
+    }
+
+    fn next(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let offset = to_unit_float(hash(&[self.seed, self.pixel, dimension as u64]) as u32);
+                let x = radical_inverse(base, self.index) + offset;
+                (if x >= 1. { x - 1. } else { x }).min(ONE_MINUS_EPSILON)
+            },
+            None => self.rng.next_f32(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let x = self.next();
+        Point2::new(x, self.next())
+    }
+}
+
+// Mirrors the digits of `index` in `base` about the decimal point, e.g. 6 = 110b => 0.011b.
+fn radical_inverse(base: u32, mut index: u64) -> f32 {
+    let base = base as u64;
+    let inv_base = 1. / base as f64;
+    let mut reversed = 0u64;
+    let mut inv_base_n = 1.;
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + (index - next * base);
+        inv_base_n *= inv_base;
+        index = next;
+    }
+    (reversed as f64 * inv_base_n) as f32
+}
@@ -0,0 +1,32 @@
+use cgmath::*;
+use raytracing::samplers::Sampler;
+use raytracing::util::random::Rng;
+
+// Plain uniform random numbers, with no attempt to spread them out.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler { seed, rng: Rng::for_sample(seed, 0, 0) }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    // Every number is independent of the others, so the dimension doesn't matter
+    fn set_dimension(&mut self, _dimension: u32) {}
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.next_f32()
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        Point2::new(self.rng.next_f32(), self.rng.next_f32())
+    }
+}
@@ -0,0 +1,117 @@
+use cgmath::*;
+use raytracing::util::random::mix_bits;
+use std::str::FromStr;
+
+mod halton;
+pub use self::halton::HaltonSampler;
+
+mod independent;
+pub use self::independent::IndependentSampler;
+
+mod sobol;
+pub use self::sobol::SobolSampler;
+
+mod stratified;
+pub use self::stratified::StratifiedSampler;
+
+// Hands out the random numbers for each sample of a pixel as a sequence of dimensions, which the
+// low-discrepancy samplers distribute evenly across a pixel's samples.
+pub trait Sampler {
+    // Starts sample `index` of `pixel` (counted row by row across the image) at dimension zero.
+    fn start_sample(&mut self, pixel: u64, index: u64);
+
+    // Skips to `dimension`, see `bounce_dimension`.
+    fn set_dimension(&mut self, dimension: u32);
+
+    // A number in [0, 1), taking one dimension.
+    fn get_1d(&mut self) -> f32;
+
+    // A point in [0, 1)², taking two dimensions.
+    fn get_2d(&mut self) -> Point2<f32>;
+}
+
+// Dimensions are laid out with the camera's first, then a fixed block for each bounce, so that a
+// dimension always feeds the same decision whatever was sampled before it along the path. The
+// camera takes the position in the pixel and on the lens, then the wavelength in spectral mode.
+pub const CAMERA_DIMENSIONS: u32 = 5;
+pub const WAVELENGTH_DIMENSION: u32 = 4;
+pub const BOUNCE_DIMENSIONS: u32 = 8;
+// Offsets within a bounce's block: picking a light and a point on it, scattering, then Russian
+// roulette
+pub const LIGHT_DIMENSIONS: u32 = 0;
+pub const SCATTER_DIMENSIONS: u32 = 4;
+pub const ROULETTE_DIMENSION: u32 = 7;
+
+pub fn bounce_dimension(depth: u32, offset: u32) -> u32 {
+    CAMERA_DIMENSIONS + depth * BOUNCE_DIMENSIONS + offset
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerType {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl FromStr for SamplerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SamplerType, String> {
+        match s {
+            "independent" => Ok(SamplerType::Independent),
+            "stratified" => Ok(SamplerType::Stratified),
+            "halton" => Ok(SamplerType::Halton),
+            "sobol" => Ok(SamplerType::Sobol),
+            _ => Err(format!("unknown sampler '{}' (expected independent, stratified, halton or sobol)", s)),
+        }
+    }
+}
+
+// `samples_per_pass` is the number of samples each pixel takes per pass, which the stratified
+// sampler divides its strata between.
+pub fn create_sampler(sampler_type: SamplerType, seed: u64, samples_per_pass: u32) -> Box<dyn Sampler> {
+    match sampler_type {
+        SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+        SamplerType::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pass)),
+        SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+        SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+    }
+}
+
+// The largest float below one
+const ONE_MINUS_EPSILON: f32 = 0.99999994;
+
+// Combines the values into a single well mixed key, e.g. to seed a scramble per pixel and dimension.
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |h, &v| mix_bits(h ^ v))
+}
+
+// Top 24 bits of a random integer as a float in [0, 1).
+fn to_unit_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1. / (1u32 << 24) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_in_the_unit_interval() {
+        for &sampler_type in &[SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol] {
+            let mut sampler = create_sampler(sampler_type, 7, 16);
+            for pixel in 0..16 {
+                for index in 0..64 {
+                    sampler.start_sample(pixel, index);
+                    for depth in 0..4 {
+                        sampler.set_dimension(bounce_dimension(depth, LIGHT_DIMENSIONS));
+                        let (x, p) = (sampler.get_1d(), sampler.get_2d());
+                        for &value in &[x, p.x, p.y] {
+                            assert!((0. ..1.).contains(&value), "{:?} sampler gave {}", sampler_type, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use cgmath::*;
+use raytracing::samplers::{hash, to_unit_float, Sampler};
+
+// Owen-scrambled Sobol points, following Burley's "Practical Hash-based Owen Scrambling". Every
+// 2D pair of dimensions uses the first two Sobol dimensions (which are well distributed together),
+// decorrelated from the other pairs by shuffling the sample order and scrambling the values with
+// hashes seeded per pixel and dimension. The scrambling keeps the sequence's stratification, and
+// also breaks up the structure that shows between neighbouring pixels.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler { seed, pixel: 0, index: 0, dimension: 0 }
+    }
+
+    // The shuffled sample index, and seeds for scrambling each coordinate
+    fn scramble_seeds(&mut self, dimensions: u32) -> (u32, u32, u32) {
+        let key = hash(&[self.seed, self.pixel, self.dimension as u64]);
+        self.dimension += dimensions;
+        let index = nested_uniform_scramble(self.index, key as u32);
+        (index, (key >> 32) as u32, hash(&[key]) as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (index, x_seed, _) = self.scramble_seeds(1);
+        to_unit_float(nested_uniform_scramble(index.reverse_bits(), x_seed))
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let (index, x_seed, y_seed) = self.scramble_seeds(2);
+        let x = nested_uniform_scramble(index.reverse_bits(), x_seed);
+        let y = nested_uniform_scramble(sobol_second_dimension(index), y_seed);
+        Point2::new(to_unit_float(x), to_unit_float(y))
+    }
+}
+
+// The second Sobol dimension (the first is just the index with its bits reversed), as a 0.32 fixed
+// point fraction.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// A hash where each bit only depends on the bits below it, with the constants from Nathan Vegdahl's
+// improvement on Laine and Karras's original.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x ^= x.wrapping_mul(0x3d20_adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x0552_6c56);
+    x ^= x.wrapping_mul(0x53a2_2864);
+    x
+}
+
+// Owen scrambling of a 0.32 fixed point fraction: each bit is flipped depending on the bits above
+// it, which randomises the points while keeping them stratified.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_stratum_is_hit_once() {
+        // Any power of two samples from the start cover its strata, in 1D and in every grid of
+        // that many cells
+        let mut sampler = SobolSampler::new(7);
+        for pixel in 0..4 {
+            let mut strata_1d = [0; 16];
+            let mut strata_2d = [[0; 16]; 5];
+            for index in 0..16 {
+                sampler.start_sample(pixel, index);
+                sampler.set_dimension(5);
+                strata_1d[(sampler.get_1d() * 16.) as usize] += 1;
+                let p = sampler.get_2d();
+                for (log_width, strata) in strata_2d.iter_mut().enumerate() {
+                    let (columns, rows) = (1 << log_width, 16 >> log_width);
+                    strata[(p.y * rows as f32) as usize * columns + (p.x * columns as f32) as usize] += 1;
+                }
+            }
+            assert_eq!(strata_1d, [1; 16], "pixel {} doesn't hit each 1D stratum once", pixel);
+            for (log_width, strata) in strata_2d.iter().enumerate() {
+                assert_eq!(*strata, [1; 16], "pixel {} doesn't hit each {}x{} stratum once", pixel, 1 << log_width, 16 >> log_width);
+            }
+        }
+    }
+}
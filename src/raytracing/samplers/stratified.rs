@@ -0,0 +1,118 @@
+use cgmath::*;
+use raytracing::samplers::{hash, Sampler, ONE_MINUS_EPSILON};
+use raytracing::util::random::Rng;
+
+// Jittered stratification of the samples each pixel takes in a pass: every dimension is split into
+// as many strata as there are samples (a square grid of them for 2D), each sample falls in a
+// different one, and the strata are shuffled independently for each dimension. Passes are
+// stratified separately, so this only helps with several samples per pass.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pass: u32,
+    pixel: u64,
+    index: u64,
+    dimension: u32,
+    rng: Rng,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pass: u32) -> StratifiedSampler {
+        StratifiedSampler { seed, samples_per_pass: samples_per_pass.max(1), pixel: 0, index: 0, dimension: 0, rng: Rng::for_sample(seed, 0, 0) }
+    }
+
+    // The stratum, out of `count`, that this sample falls in for the current dimension. A pass's
+    // samples are split into groups of `count` (all but the last complete), each covering the
+    // strata in a random order.
+    fn stratum(&self, count: u32) -> u32 {
+        let samples_per_pass = self.samples_per_pass as u64;
+        let pass = self.index / samples_per_pass;
+        let in_pass = (self.index % samples_per_pass) as u32;
+        let (group, in_group) = (in_pass / count, in_pass % count);
+        let key = hash(&[self.seed, self.pixel, pass, group as u64, self.dimension as u64]);
+        permute(in_group, count, key as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let count = self.samples_per_pass;
+        let stratum = self.stratum(count);
+        self.dimension += 1;
+        ((stratum as f32 + self.rng.next_f32()) / count as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let size = ((self.samples_per_pass as f32).sqrt() as u32).max(1);
+        let stratum = self.stratum(size * size);
+        self.dimension += 2;
+        let x = ((stratum % size) as f32 + self.rng.next_f32()) / size as f32;
+        let y = ((stratum / size) as f32 + self.rng.next_f32()) / size as f32;
+        Point2::new(x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+// Element `i` of a random permutation of 0..count chosen by `seed`, without storing it. From
+// Kensler's "Correlated Multi-Jittered Sampling", cycle walking past values outside the range.
+fn permute(mut i: u32, count: u32, seed: u32) -> u32 {
+    let mut w = count - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < count {
+            return (i.wrapping_add(seed)) % count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_stratum_is_hit_once_per_pass() {
+        let mut sampler = StratifiedSampler::new(7, 16);
+        for pass in 0..4 {
+            let (mut strata_1d, mut strata_2d) = ([0; 16], [0; 16]);
+            for index in pass * 16..(pass + 1) * 16 {
+                sampler.start_sample(3, index);
+                strata_1d[(sampler.get_1d() * 16.) as usize] += 1;
+                let p = sampler.get_2d();
+                strata_2d[(p.y * 4.) as usize * 4 + (p.x * 4.) as usize] += 1;
+            }
+            assert_eq!(strata_1d, [1; 16], "pass {} doesn't hit each 1D stratum once", pass);
+            assert_eq!(strata_2d, [1; 16], "pass {} doesn't hit each 2D stratum once", pass);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use cgmath::*;
+use raytracing::cameras::util::{create_camera};
+use raytracing::materials::{Dialectric, DiffuseLight, Lambertian, Metal, RefractiveIndex};
+use raytracing::scenes::Scene;
+use raytracing::shapes::{Cuboid, Plane, RectXY, RectXZ, RectYZ, Sphere};
+use raytracing::{HitableCollection};
+
+pub fn test() -> Scene {
+    let shapes: HitableCollection = vec![
+        Box::new(Sphere { origin: Point3::new(0., 0., 0.), radius: 0.5, material: Box::new(Lambertian { albedo: vec3(0.1, 0.2, 0.5).into() }) }),
+        Box::new(Plane { origin: Point3::new(0., -0.5, 0.), normal: vec3(0., 1., 0.), material: Box::new(Lambertian { albedo: vec3(0.2, 0.5, 0.2).into() }) }),
+        Box::new(Sphere { origin: Point3::new(1., 0., 0.), radius: 0.5, material: Box::new(Metal { albedo: vec3(0.8, 0.6, 0.2).into(), fuzziness: 0.3 }) }),
+        Box::new(Sphere { origin: Point3::new(-1., 0., 0.), radius: 0.5, material: Box::new(Dialectric { refractive_index: RefractiveIndex::Constant(1.5) }) }),
+        //Box::new(Sphere { origin: Point3::new(-1., 0., 0.), radius: -0.45, material: Box::new(Dialectric { refractive_index: RefractiveIndex::Constant(1.5) }) }),
+        Box::new(RectXZ { x0: -0.5, x1: 0.5, z0: -0.5, z1: 0.5, k: 2., material: Box::new(DiffuseLight { colour: vec3(4., 4., 4.).into() }) }),
+    ];
+    let material_indices = vec![1, 2, 3, 4, 5];
+    let camera = create_camera(
+        Point3::new(0., 0.2, 1.75),
+        Point3::new(0., 0., 0.),
+        vec3(0., 1., 0.),
+        Deg(60.),
+        0.01,
+        100.,
+        0.2,
+    );
+    Scene { shapes, material_indices, camera, environment: None, assets: Vec::new() }
+}
+
+pub fn cornell_box() -> Scene {
+    let small_box_transform = Matrix4::from_translation(vec3(212.5, 82.5, 147.5)) * Matrix4::from_angle_y(Deg(-18.));
+    let tall_box_transform = Matrix4::from_translation(vec3(347.5, 165., 377.5)) * Matrix4::from_angle_y(Deg(15.));
+    let shapes: HitableCollection = vec![
+        // Walls
+        Box::new(RectYZ { y0: 0., y1: 555., z0: 0., z1: 555., k: 555., material: Box::new(Lambertian { albedo: vec3(0.12, 0.45, 0.15).into() }) }),
+        Box::new(RectYZ { y0: 0., y1: 555., z0: 0., z1: 555., k: 0., material: Box::new(Lambertian { albedo: vec3(0.65, 0.05, 0.05).into() }) }),
+        Box::new(RectXZ { x0: 0., x1: 555., z0: 0., z1: 555., k: 0., material: Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73).into() }) }),
+        Box::new(RectXZ { x0: 0., x1: 555., z0: 0., z1: 555., k: 555., material: Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73).into() }) }),
+        Box::new(RectXY { x0: 0., x1: 555., y0: 0., y1: 555., k: 555., material: Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73).into() }) }),
+        // Light
+        Box::new(RectXZ { x0: 213., x1: 343., z0: 227., z1: 332., k: 554., material: Box::new(DiffuseLight { colour: vec3(15., 15., 15.).into() }) }),
+        // Boxes
+        Box::new(Cuboid::new(small_box_transform, vec3(165., 165., 165.), Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73).into() }))),
+        Box::new(Cuboid::new(tall_box_transform, vec3(165., 330., 165.), Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73).into() }))),
+    ];
+    // Green, red, white and the light
+    let material_indices = vec![1, 2, 3, 3, 3, 4, 3, 3];
+    let camera = create_camera(
+        Point3::new(278., 278., -800.),
+        Point3::new(278., 278., 0.),
+        vec3(0., 1., 0.),
+        Deg(40.),
+        0.01,
+        100.,
+        0.,
+    );
+    Scene { shapes, material_indices, camera, environment: None, assets: Vec::new() }
+}
@@ -0,0 +1,634 @@
+use cgmath::*;
+use raytracing::cameras::Camera;
+use raytracing::environments::{ConstantEnvironment, GradientEnvironment, ImageEnvironment, SkyEnvironment};
+use raytracing::materials::{Dialectric, DiffuseLight, Lambertian, Metal, RefractiveIndex};
+use raytracing::scenes::{load_obj, Scene};
+use raytracing::shapes::{Cuboid, Plane, RectXY, RectXZ, RectYZ, Sphere, Transformed, TriangleMesh};
+use raytracing::{BoxedEnvironment, BoxedHitable, BoxedTexture, Hitable, HitableCollection, Material};
+use raytracing::textures::{CheckerTexture, ImageTexture, NoiseTexture, NoiseType, Perlin};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tobj;
+use toml;
+use toml::Spanned;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Obj(tobj::LoadError),
+    Invalid { line: usize, column: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io(ref err) => write!(f, "{}", err),
+            SceneError::Parse(ref err) => write!(f, "{}", err),
+            SceneError::Obj(ref err) => write!(f, "{}", err),
+            SceneError::Invalid { line, column, ref message } => write!(f, "line {}, column {}: {}", line, column, message),
+        }
+    }
+}
+
+impl error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> SceneError {
+        SceneError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(err: toml::de::Error) -> SceneError {
+        SceneError::Parse(err)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    camera: CameraDescription,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDescription>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDescription>>,
+    #[serde(default)]
+    shapes: Vec<Spanned<ShapeDescription>>,
+    environment: Option<Spanned<EnvironmentDescription>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    eye: [f32; 3],
+    target: [f32; 3],
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+    fov: f32,
+    #[serde(default = "default_near")]
+    near: f32,
+    #[serde(default = "default_far")]
+    far: f32,
+    #[serde(default)]
+    aperture: f32,
+    focal_distance: Option<f32>,
+}
+
+fn default_up() -> [f32; 3] { [0., 1., 0.] }
+fn default_near() -> f32 { 0.01 }
+fn default_far() -> f32 { 100. }
+
+// Either a constant colour or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureReference {
+    Colour([f32; 3]),
+    Named(String),
+}
+
+// A constant index, the name of a glass, or Cauchy or Sellmeier coefficients for wavelengths in µm
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RefractiveIndexDescription {
+    Constant(f32),
+    Named(String),
+    // A, B and optionally C
+    Cauchy { cauchy: Vec<f32> },
+    Sellmeier { sellmeier: SellmeierDescription },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDescription {
+    b: Vec<f32>,
+    c: Vec<f32>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum NoiseTypeDescription {
+    Perlin,
+    Turbulence,
+    Marble,
+}
+
+fn default_noise_type() -> NoiseTypeDescription { NoiseTypeDescription::Perlin }
+fn default_one() -> f32 { 1. }
+fn default_white() -> [f32; 3] { [1., 1., 1.] }
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Constant {
+        #[serde(alias = "color")]
+        colour: [f32; 3],
+    },
+    Checker {
+        odd: TextureReference,
+        even: TextureReference,
+        #[serde(default = "default_one")]
+        scale: f32,
+    },
+    Image { file: String },
+    Noise {
+        #[serde(default = "default_noise_type")]
+        noise: NoiseTypeDescription,
+        #[serde(default = "default_white", alias = "color")]
+        colour: [f32; 3],
+        #[serde(default = "default_one")]
+        scale: f32,
+        #[serde(default)]
+        seed: u32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian { albedo: TextureReference },
+    Metal {
+        albedo: TextureReference,
+        #[serde(default)]
+        fuzziness: f32,
+    },
+    #[serde(alias = "dielectric")]
+    Dialectric { refractive_index: RefractiveIndexDescription },
+    DiffuseLight {
+        #[serde(alias = "color")]
+        colour: TextureReference,
+    },
+}
+
+fn default_sky() -> [f32; 3] { [0.5, 0.7, 1.] }
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDescription {
+    Constant {
+        #[serde(alias = "color")]
+        colour: [f32; 3],
+    },
+    Gradient {
+        #[serde(default = "default_white")]
+        bottom: [f32; 3],
+        #[serde(default = "default_sky")]
+        top: [f32; 3],
+    },
+    // An equirectangular .hdr or .exr image
+    Image {
+        file: String,
+        // Degrees about the Y axis
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default = "default_one")]
+        intensity: f32,
+    },
+    // Daylight with the sun `elevation` degrees above the horizon, at `azimuth` degrees clockwise
+    // from -Z
+    Sky {
+        elevation: f32,
+        #[serde(default)]
+        azimuth: f32,
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+        #[serde(default = "default_one")]
+        intensity: f32,
+    },
+}
+
+fn default_turbidity() -> f32 { 3. }
+
+// Guards against textures that (indirectly) reference themselves
+const MAX_TEXTURE_NESTING: u32 = 16;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformDescription {
+    #[serde(default)]
+    translate: [f32; 3],
+    // Euler angles in degrees, applied in X, Y, Z order
+    #[serde(default)]
+    rotate: [f32; 3],
+    #[serde(default = "default_scale")]
+    scale: [f32; 3],
+}
+
+fn default_scale() -> [f32; 3] { [1., 1., 1.] }
+
+// Every shape can be placed with an optional transform
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDescription {
+    Sphere {
+        origin: [f32; 3],
+        radius: f32,
+        material: String,
+        transform: Option<TransformDescription>,
+    },
+    Plane {
+        origin: [f32; 3],
+        normal: [f32; 3],
+        material: String,
+        transform: Option<TransformDescription>,
+    },
+    #[serde(rename = "rect_xy")]
+    RectXY {
+        x0: f32, x1: f32, y0: f32, y1: f32, k: f32,
+        material: String,
+        transform: Option<TransformDescription>,
+    },
+    #[serde(rename = "rect_xz")]
+    RectXZ {
+        x0: f32, x1: f32, z0: f32, z1: f32, k: f32,
+        material: String,
+        transform: Option<TransformDescription>,
+    },
+    #[serde(rename = "rect_yz")]
+    RectYZ {
+        y0: f32, y1: f32, z0: f32, z1: f32, k: f32,
+        material: String,
+        transform: Option<TransformDescription>,
+    },
+    Cuboid {
+        dimensions: [f32; 3],
+        material: String,
+        transform: Option<TransformDescription>,
+    },
+    // Each OBJ file is loaded once and shared between all the shapes referencing it (with the
+    // same material override).
+    Obj {
+        file: String,
+        // Overrides the materials from the OBJ's MTL files
+        material: Option<String>,
+        transform: Option<TransformDescription>,
+    },
+}
+
+fn point(p: [f32; 3]) -> Point3<f32> {
+    Point3::new(p[0], p[1], p[2])
+}
+
+fn vector(v: [f32; 3]) -> Vector3<f32> {
+    vec3(v[0], v[1], v[2])
+}
+
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+impl CameraDescription {
+    fn build(&self) -> Camera {
+        let eye = point(self.eye);
+        let target = point(self.target);
+        Camera {
+            eye,
+            target,
+            up: vector(self.up),
+            fov: Deg(self.fov),
+            near: self.near,
+            far: self.far,
+            aperture: self.aperture,
+            focal_distance: self.focal_distance.unwrap_or_else(|| (target - eye).magnitude()),
+        }
+    }
+}
+
+impl TransformDescription {
+    fn build(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(vector(self.translate))
+            * Matrix4::from_angle_z(Deg(self.rotate[2]))
+            * Matrix4::from_angle_y(Deg(self.rotate[1]))
+            * Matrix4::from_angle_x(Deg(self.rotate[0]))
+            * Matrix4::from_nonuniform_scale(self.scale[0], self.scale[1], self.scale[2])
+    }
+}
+
+// OBJ file and material override
+type MeshKey = (String, Option<String>);
+// A mesh shared between the shapes placing it, and the number of its material
+type SharedMesh = (Arc<TriangleMesh>, u32);
+
+struct Builder<'a> {
+    source: &'a str,
+    base_dir: &'a Path,
+    textures: &'a BTreeMap<String, Spanned<TextureDescription>>,
+    materials: &'a BTreeMap<String, Spanned<MaterialDescription>>,
+    // Named materials are numbered from 1 in the order they're written, then those from MTL files
+    // (by OBJ file and position in its MTL libraries) follow on as they're loaded
+    material_indices: HashMap<&'a str, u32>,
+    mtl_material_indices: RefCell<HashMap<(String, Option<usize>), u32>>,
+    images: RefCell<HashMap<String, Arc<ImageTexture>>>,
+    meshes: RefCell<HashMap<MeshKey, Vec<SharedMesh>>>,
+    assets: RefCell<Vec<PathBuf>>,
+}
+
+impl<'a> Builder<'a> {
+    fn invalid(&self, offset: usize, message: String) -> SceneError {
+        let (line, column) = line_and_column(self.source, offset);
+        SceneError::Invalid { line, column, message }
+    }
+
+    fn image(&self, offset: usize, file: &str) -> Result<Arc<ImageTexture>, SceneError> {
+        if let Some(image) = self.images.borrow().get(file) {
+            return Ok(image.clone());
+        }
+        let image = ImageTexture::load(self.base_dir.join(file)).map_err(|err| {
+            self.invalid(offset, format!("unable to load '{}': {}", file, err))
+        })?;
+        self.assets.borrow_mut().push(self.base_dir.join(file));
+        let image = Arc::new(image);
+        self.images.borrow_mut().insert(file.to_string(), image.clone());
+        Ok(image)
+    }
+
+    fn texture(&self, offset: usize, texture: &TextureReference, nesting: u32) -> Result<BoxedTexture, SceneError> {
+        let name = match *texture {
+            TextureReference::Colour(colour) => return Ok(vector(colour).into()),
+            TextureReference::Named(ref name) => name,
+        };
+        let description = match self.textures.get(name) {
+            Some(description) => description,
+            None => return Err(self.invalid(offset, format!("unknown texture '{}'", name))),
+        };
+        if nesting > MAX_TEXTURE_NESTING {
+            return Err(self.invalid(description.span().start, format!("texture '{}' references itself", name)));
+        }
+
+        let offset = description.span().start;
+        let texture: BoxedTexture = match *description.get_ref() {
+            TextureDescription::Constant { colour } => vector(colour).into(),
+            TextureDescription::Checker { ref odd, ref even, scale } => Box::new(CheckerTexture {
+                odd: self.texture(offset, odd, nesting + 1)?,
+                even: self.texture(offset, even, nesting + 1)?,
+                scale,
+            }),
+            TextureDescription::Image { ref file } => Box::new(self.image(offset, file)?),
+            TextureDescription::Noise { noise, colour, scale, seed } => Box::new(NoiseTexture {
+                noise: Perlin::new(seed),
+                noise_type: match noise {
+                    NoiseTypeDescription::Perlin => NoiseType::Perlin,
+                    NoiseTypeDescription::Turbulence => NoiseType::Turbulence,
+                    NoiseTypeDescription::Marble => NoiseType::Marble,
+                },
+                colour: vector(colour),
+                scale,
+            }),
+        };
+        Ok(texture)
+    }
+
+    fn material(&self, offset: usize, name: &str) -> Result<Material, SceneError> {
+        let description = match self.materials.get(name) {
+            Some(description) => description,
+            None => return Err(self.invalid(offset, format!("unknown material '{}'", name))),
+        };
+        let offset = description.span().start;
+        let material: Material = match *description.get_ref() {
+            MaterialDescription::Lambertian { ref albedo } => Box::new(Lambertian { albedo: self.texture(offset, albedo, 0)? }),
+            MaterialDescription::Metal { ref albedo, fuzziness } => Box::new(Metal { albedo: self.texture(offset, albedo, 0)?, fuzziness }),
+            MaterialDescription::Dialectric { ref refractive_index } => Box::new(Dialectric { refractive_index: self.refractive_index(offset, refractive_index)? }),
+            MaterialDescription::DiffuseLight { ref colour } => Box::new(DiffuseLight { colour: self.texture(offset, colour, 0)? }),
+        };
+        Ok(material)
+    }
+
+    fn refractive_index(&self, offset: usize, description: &RefractiveIndexDescription) -> Result<RefractiveIndex, SceneError> {
+        let refractive_index = match *description {
+            RefractiveIndexDescription::Constant(index) => RefractiveIndex::Constant(index),
+            RefractiveIndexDescription::Named(ref name) => match RefractiveIndex::named(name) {
+                Some(refractive_index) => refractive_index,
+                None => return Err(self.invalid(offset, format!("unknown glass '{}' (expected bk7, fused_silica, diamond or water)", name))),
+            },
+            RefractiveIndexDescription::Cauchy { ref cauchy } => match cauchy[..] {
+                [a, b] => RefractiveIndex::Cauchy { a, b, c: 0. },
+                [a, b, c] => RefractiveIndex::Cauchy { a, b, c },
+                _ => return Err(self.invalid(offset, "cauchy takes two or three coefficients".to_string())),
+            },
+            RefractiveIndexDescription::Sellmeier { sellmeier: SellmeierDescription { ref b, ref c } } => {
+                if b.is_empty() || b.len() != c.len() {
+                    return Err(self.invalid(offset, "sellmeier needs as many c coefficients as b".to_string()));
+                }
+                RefractiveIndex::Sellmeier(b.iter().cloned().zip(c.iter().cloned()).collect())
+            },
+        };
+        Ok(refractive_index)
+    }
+
+    fn environment(&self, environment: &Spanned<EnvironmentDescription>) -> Result<BoxedEnvironment, SceneError> {
+        let offset = environment.span().start;
+        let environment: BoxedEnvironment = match *environment.get_ref() {
+            EnvironmentDescription::Constant { colour } => Box::new(ConstantEnvironment { colour: vector(colour) }),
+            EnvironmentDescription::Gradient { bottom, top } => Box::new(GradientEnvironment { bottom: vector(bottom), top: vector(top) }),
+            EnvironmentDescription::Image { ref file, rotation, intensity } => {
+                let image = ImageEnvironment::load(self.base_dir.join(file), Deg(rotation), intensity).map_err(|err| {
+                    self.invalid(offset, format!("unable to load '{}': {}", file, err))
+                })?;
+                self.assets.borrow_mut().push(self.base_dir.join(file));
+                Box::new(image)
+            },
+            EnvironmentDescription::Sky { elevation, azimuth, turbidity, intensity } => {
+                if !(0. ..=90.).contains(&elevation) {
+                    return Err(self.invalid(offset, "sun elevation must be between 0 and 90 degrees".to_string()));
+                }
+                if !(1.7..=10.).contains(&turbidity) {
+                    return Err(self.invalid(offset, "turbidity must be between 1.7 and 10".to_string()));
+                }
+                Box::new(SkyEnvironment::new(Deg(elevation), Deg(azimuth), turbidity, intensity))
+            },
+        };
+        Ok(environment)
+    }
+
+    fn transform(&self, offset: usize, transform: &TransformDescription) -> Result<Matrix4<f32>, SceneError> {
+        let transform = transform.build();
+        if transform.invert().is_none() {
+            return Err(self.invalid(offset, "transform is not invertible".to_string()));
+        }
+        Ok(transform)
+    }
+
+    fn place<H: Hitable + Send + Sync + 'static>(&self, offset: usize, shape: H, transform: &Option<TransformDescription>) -> Result<BoxedHitable, SceneError> {
+        Ok(match *transform {
+            Some(ref transform) => Box::new(Transformed::new(shape, self.transform(offset, transform)?)),
+            None => Box::new(shape),
+        })
+    }
+
+    fn material_index(&self, name: &str) -> u32 {
+        self.material_indices.get(name).cloned().unwrap_or(0)
+    }
+
+    fn meshes(&self, offset: usize, file: &str, material: &Option<String>) -> Result<Vec<SharedMesh>, SceneError> {
+        let key = (file.to_string(), material.clone());
+        if let Some(meshes) = self.meshes.borrow().get(&key) {
+            return Ok(meshes.clone());
+        }
+
+        let path = self.base_dir.join(file);
+        let (meshes, libraries) = load_obj(&path).map_err(|err| {
+            self.invalid(offset, format!("unable to load '{}': {}", file, err))
+        })?;
+        // The same file is loaded again for each material it's given
+        if !self.assets.borrow().contains(&path) {
+            let mut assets = self.assets.borrow_mut();
+            assets.push(path);
+            assets.extend(libraries);
+        }
+        let mut shared = Vec::with_capacity(meshes.len());
+        for (mut mesh, mtl_material) in meshes {
+            let material_index = match *material {
+                Some(ref material) => {
+                    mesh.material = self.material(offset, material)?;
+                    self.material_index(material)
+                },
+                None => {
+                    let mut mtl_material_indices = self.mtl_material_indices.borrow_mut();
+                    let next = (self.material_indices.len() + mtl_material_indices.len()) as u32 + 1;
+                    *mtl_material_indices.entry((file.to_string(), mtl_material)).or_insert(next)
+                },
+            };
+            shared.push((Arc::new(TriangleMesh::new(mesh)), material_index));
+        }
+        self.meshes.borrow_mut().insert(key, shared.clone());
+        Ok(shared)
+    }
+
+    // Adds the shape (or each of an OBJ's meshes) to `shapes`, and its material's number to
+    // `material_indices`.
+    fn shapes(&self, shape: &Spanned<ShapeDescription>, shapes: &mut HitableCollection, material_indices: &mut Vec<u32>) -> Result<(), SceneError> {
+        let offset = shape.span().start;
+        let (shape, material) = match *shape.get_ref() {
+            ShapeDescription::Sphere { origin, radius, ref material, ref transform } => {
+                let sphere = Sphere { origin: point(origin), radius, material: self.material(offset, material)? };
+                (self.place(offset, sphere, transform)?, material)
+            },
+            ShapeDescription::Plane { origin, normal, ref material, ref transform } => {
+                let normal = vector(normal);
+                if normal.magnitude2() == 0. {
+                    return Err(self.invalid(offset, "plane normal must not be zero".to_string()));
+                }
+                let plane = Plane { origin: point(origin), normal: normal.normalize(), material: self.material(offset, material)? };
+                (self.place(offset, plane, transform)?, material)
+            },
+            ShapeDescription::RectXY { x0, x1, y0, y1, k, ref material, ref transform } => {
+                let rect = RectXY { x0, x1, y0, y1, k, material: self.material(offset, material)? };
+                (self.place(offset, rect, transform)?, material)
+            },
+            ShapeDescription::RectXZ { x0, x1, z0, z1, k, ref material, ref transform } => {
+                let rect = RectXZ { x0, x1, z0, z1, k, material: self.material(offset, material)? };
+                (self.place(offset, rect, transform)?, material)
+            },
+            ShapeDescription::RectYZ { y0, y1, z0, z1, k, ref material, ref transform } => {
+                let rect = RectYZ { y0, y1, z0, z1, k, material: self.material(offset, material)? };
+                (self.place(offset, rect, transform)?, material)
+            },
+            ShapeDescription::Cuboid { dimensions, ref material, ref transform } => {
+                let cuboid = Cuboid::centred(vector(dimensions), self.material(offset, material)?);
+                (self.place(offset, cuboid, transform)?, material)
+            },
+            ShapeDescription::Obj { ref file, ref material, ref transform } => {
+                for (mesh, material_index) in self.meshes(offset, file, material)? {
+                    shapes.push(self.place(offset, mesh, transform)?);
+                    material_indices.push(material_index);
+                }
+                return Ok(());
+            },
+        };
+        shapes.push(shape);
+        material_indices.push(self.material_index(material));
+        Ok(())
+    }
+}
+
+// Files referenced by the scene (e.g. OBJ meshes) are resolved relative to `base_dir`.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let description: SceneDescription = toml::from_str(source)?;
+    let mut material_names: Vec<(&str, usize)> = description.materials.iter().map(|(name, material)| (name.as_str(), material.span().start)).collect();
+    material_names.sort_by_key(|&(_, offset)| offset);
+    let builder = Builder {
+        source,
+        base_dir,
+        textures: &description.textures,
+        materials: &description.materials,
+        material_indices: material_names.iter().enumerate().map(|(i, &(name, _))| (name, i as u32 + 1)).collect(),
+        mtl_material_indices: RefCell::new(HashMap::new()),
+        images: RefCell::new(HashMap::new()),
+        meshes: RefCell::new(HashMap::new()),
+        assets: RefCell::new(Vec::new()),
+    };
+    let mut shapes: HitableCollection = Vec::with_capacity(description.shapes.len());
+    let mut material_indices = Vec::with_capacity(description.shapes.len());
+    for shape in &description.shapes {
+        builder.shapes(shape, &mut shapes, &mut material_indices)?;
+    }
+    let environment = match description.environment {
+        Some(ref environment) => Some(builder.environment(environment)?),
+        None => None,
+    };
+    Ok(Scene { shapes, material_indices, camera: description.camera.build(), environment, assets: builder.assets.into_inner() })
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    parse_scene(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "\n[camera]\neye = [0, 0, 1]\ntarget = [0, 0, 0]\nfov = 60\n";
+
+    // The line, column and message of the error loading `source`, followed by a camera.
+    fn error_position(source: &str) -> (usize, usize, String) {
+        match parse_scene(&format!("{}{}", source, CAMERA), Path::new("")) {
+            Err(SceneError::Invalid { line, column, message }) => (line, column, message),
+            Err(err) => panic!("expected an invalid scene, got '{}'", err),
+            Ok(_) => panic!("expected an invalid scene"),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_shape_or_material_at_fault() {
+        let (line, column, message) = error_position(r#"[materials.red]
+type = "lambertian"
+albedo = [1, 0, 0]
+
+[[shapes]]
+type = "sphere"
+origin = [0, 0, 0]
+radius = 1
+material = "missing"
+"#);
+        assert_eq!((line, column, message.as_str()), (5, 1, "unknown material 'missing'"));
+
+        let (line, column, message) = error_position(r#"[materials.red]
+type = "lambertian"
+albedo = "missing"
+
+[[shapes]]
+type = "sphere"
+origin = [0, 0, 0]
+radius = 1
+material = "red"
+"#);
+        assert_eq!((line, column, message.as_str()), (1, 1, "unknown texture 'missing'"));
+
+        let (line, column, _) = error_position(r#"shapes = [
+    { type = "sphere", origin = [0, 0, 0], radius = 1, material = "missing" },
+]
+"#);
+        assert_eq!((line, column), (2, 5));
+    }
+}
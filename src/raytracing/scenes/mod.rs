@@ -0,0 +1,21 @@
+mod builtin;
+pub use self::builtin::{cornell_box, test};
+
+mod loader;
+pub use self::loader::{load_scene, parse_scene, SceneError};
+
+mod obj;
+pub use self::obj::{load_obj, material_from_mtl};
+
+mod types;
+pub use self::types::Scene;
+
+pub const BUILTIN_SCENES: &[&str] = &["cornell", "test"];
+
+pub fn builtin(name: &str) -> Option<Scene> {
+    match name {
+        "cornell" => Some(cornell_box()),
+        "test" => Some(test()),
+        _ => None,
+    }
+}
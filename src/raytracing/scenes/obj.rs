@@ -0,0 +1,92 @@
+use cgmath::*;
+use raytracing::Material;
+use raytracing::materials::{Dialectric, DiffuseLight, Lambertian, Metal, RefractiveIndex};
+use raytracing::scenes::SceneError;
+use raytracing::shapes::{Mesh};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use tobj;
+
+// Each mesh with the position of its material in the MTL libraries
+type ObjMeshes = Vec<(Mesh, Option<usize>)>;
+
+fn vector(v: [f32; 3]) -> Vector3<f32> {
+    vec3(v[0], v[1], v[2])
+}
+
+fn default_material() -> Material {
+    Box::new(Lambertian { albedo: vec3(0.73, 0.73, 0.73).into() })
+}
+
+// Maps the MTL parameters onto the closest of our materials: emissive surfaces become lights,
+// transparent ones (dissolve or a refraction illumination model) glass, reflective ones (an
+// illumination model with reflection, or specular with no diffuse) metal, and anything else diffuse.
+pub fn material_from_mtl(mtl: &tobj::Material) -> Material {
+    let illumination_model = mtl.illumination_model.unwrap_or(2);
+    let diffuse = vector(mtl.diffuse.unwrap_or([0.8, 0.8, 0.8]));
+    let specular = vector(mtl.specular.unwrap_or([0., 0., 0.]));
+    let emissive = vector(mtl.emissive.unwrap_or([0., 0., 0.]));
+
+    if emissive.x > 0. || emissive.y > 0. || emissive.z > 0. {
+        return Box::new(DiffuseLight { colour: emissive.into() });
+    }
+
+    let transparent = mtl.dissolve.unwrap_or(1.) < 1. || [4, 6, 7, 9].contains(&illumination_model);
+    if transparent {
+        let refractive_index = match mtl.optical_density {
+            Some(ior) if ior > 0. => ior,
+            _ => 1.5,
+        };
+        return Box::new(Dialectric { refractive_index: RefractiveIndex::Constant(refractive_index) });
+    }
+
+    let reflective = [3, 5, 8].contains(&illumination_model);
+    let has_specular = specular.x > 0. || specular.y > 0. || specular.z > 0.;
+    let has_diffuse = diffuse.x > 0. || diffuse.y > 0. || diffuse.z > 0.;
+    if has_specular && (reflective || !has_diffuse) {
+        // Map the Phong exponent (0..1000) onto fuzziness, sharper highlights giving smoother metal
+        let shininess = mtl.shininess.unwrap_or(0.).max(0.);
+        let fuzziness = (2. / (shininess + 2.)).sqrt();
+        return Box::new(Metal { albedo: specular.into(), fuzziness });
+    }
+
+    Box::new(Lambertian { albedo: diffuse.into() })
+}
+
+// Loads a Wavefront OBJ file (and any MTL libraries it references) as one mesh per object and
+// material, each with the position of its material in the MTL libraries (if it has one), along
+// with the paths of the MTL libraries.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<(ObjMeshes, Vec<PathBuf>), SceneError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|_| SceneError::Obj(tobj::LoadError::OpenFileFailed))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let libraries = RefCell::new(Vec::new());
+    let (models, materials) = tobj::load_obj_buf(&mut BufReader::new(file), &tobj::GPU_LOAD_OPTIONS, |library| {
+        let library = base_dir.join(library);
+        libraries.borrow_mut().push(library.clone());
+        tobj::load_mtl(library)
+    }).map_err(SceneError::Obj)?;
+    // A missing or broken MTL file isn't fatal, the meshes just fall back to a default material
+    let materials = materials.unwrap_or_default();
+
+    let mut meshes = Vec::with_capacity(models.len());
+    for model in models {
+        let mesh = model.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+        let positions = mesh.positions.chunks(3).map(|p| Point3::new(p[0], p[1], p[2])).collect();
+        let normals = mesh.normals.chunks(3).map(|n| vec3(n[0], n[1], n[2])).collect();
+        let uvs = mesh.texcoords.chunks(2).map(|t| Point2::new(t[0], t[1])).collect();
+        let indices = mesh.indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect();
+        let material_id = mesh.material_id.filter(|&id| id < materials.len());
+        let material = match material_id {
+            Some(id) => material_from_mtl(&materials[id]),
+            None => default_material(),
+        };
+        meshes.push((Mesh { positions, normals, uvs, indices, material }, material_id));
+    }
+    Ok((meshes, libraries.into_inner()))
+}
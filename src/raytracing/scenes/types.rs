@@ -0,0 +1,13 @@
+use raytracing::{BoxedEnvironment, Camera, HitableCollection};
+use std::path::PathBuf;
+
+pub struct Scene {
+    pub shapes: HitableCollection,
+    // The number of each shape's material, for the material index AOV
+    pub material_indices: Vec<u32>,
+    pub camera: Camera,
+    // What rays leaving the scene see, black if there's none
+    pub environment: Option<BoxedEnvironment>,
+    // The files (meshes, material libraries and images) the scene was built from
+    pub assets: Vec<PathBuf>,
+}
@@ -0,0 +1,293 @@
+use crossbeam::sync::chase_lev::{self, Steal, Stealer, Worker};
+use raytracing::{Film, PixelStats, Renderer, RenderStats};
+use raytracing::aov::AovBuffer;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    // Row by row from the top left
+    Scanline,
+    // Outwards from the centre of the image, where the subject usually is
+    Spiral,
+    // Along a Hilbert curve, so consecutive tiles are mostly neighbours
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TileOrder, String> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("unknown tile order '{}' (expected scanline, spiral or hilbert)", s)),
+        }
+    }
+}
+
+// A rectangle of the image, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Splits the image into tiles of (at most) `tile_size` pixels square, which cover every pixel
+// exactly once, listed in the given order.
+pub fn tiles(image_width: usize, image_height: usize, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let columns = image_width.div_ceil(tile_size);
+    let rows = image_height.div_ceil(tile_size);
+    let tile = |(column, row): (usize, usize)| {
+        let (x, y) = (column * tile_size, row * tile_size);
+        Tile { x, y, width: tile_size.min(image_width - x), height: tile_size.min(image_height - y) }
+    };
+    let coordinates = match order {
+        TileOrder::Scanline => (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row))).collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+    coordinates.into_iter().map(tile).collect()
+}
+
+// Walks a square spiral out from the centre tile (right, down, left, up, with the legs growing by
+// one every other turn), keeping the tiles that fall within the grid.
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let count = columns * rows;
+    let mut coordinates = Vec::with_capacity(count);
+    let (mut x, mut y) = (((columns as isize) - 1) / 2, ((rows as isize) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 0;
+    while coordinates.len() < count {
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..(leg / 2 + 1) {
+            if x >= 0 && y >= 0 && (x as usize) < columns && (y as usize) < rows {
+                coordinates.push((x as usize, y as usize));
+            }
+            x += dx;
+            y += dy;
+        }
+        leg += 1;
+    }
+    coordinates
+}
+
+// Follows the Hilbert curve over the smallest power of two square holding the grid, skipping the
+// tiles beyond its edges (where the curve leaves the grid, the next tile can be some way off).
+fn hilbert(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let size = columns.max(rows).next_power_of_two();
+    (0..size * size)
+        .map(|d| hilbert_point(size, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+// Position `d` along the Hilbert curve filling a `size` square (a power of two).
+fn hilbert_point(size: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < size {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+// What a pass samples: the pixels still `active`, each numbering its samples on from its count.
+struct Pass {
+    sample_counts: Vec<u32>,
+    active: Vec<bool>,
+}
+
+#[derive(Clone)]
+struct Job {
+    tile: usize,
+    pass: Arc<Pass>,
+}
+
+struct TileResult {
+    tile: usize,
+    stats: Vec<PixelStats>,
+    aovs: Vec<AovBuffer>,
+    ray_count: u64,
+}
+
+// A rendered tile, or the panic that stopped a thread rendering one. The other threads still hold
+// their senders, so a panic has to be passed on rather than the scheduler waiting for the tile.
+type TileOutcome = thread::Result<TileResult>;
+
+// Renders passes over the image with a pool of threads that lives as long as the scheduler. Each
+// pass, every thread is handed a run of consecutive tiles and once through them steals from the
+// end of the others' runs, so no thread sits idle while another has a run of expensive tiles.
+pub struct TileScheduler {
+    pub renderer: Arc<Renderer>,
+    tiles: Arc<Vec<Tile>>,
+    passes: Vec<Sender<Vec<Job>>>,
+    results: Receiver<TileOutcome>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl TileScheduler {
+    pub fn new(renderer: Arc<Renderer>) -> TileScheduler {
+        let settings = &renderer.settings;
+        let tiles = Arc::new(tiles(settings.image_width, settings.image_height, settings.tile_size, settings.tile_order));
+        let thread_count = settings.thread_count.max(1);
+
+        let (workers, stealers): (Vec<Worker<Job>>, Vec<Stealer<Job>>) = (0..thread_count).map(|_| chase_lev::deque()).unzip();
+        let (result_sender, results) = mpsc::channel();
+        let mut passes = Vec::with_capacity(thread_count);
+        let mut threads = Vec::with_capacity(thread_count);
+        for worker in workers {
+            let (pass_sender, pass_receiver) = mpsc::channel();
+            passes.push(pass_sender);
+            let renderer = renderer.clone();
+            let tiles = tiles.clone();
+            let stealers = stealers.clone();
+            let results = result_sender.clone();
+            threads.push(thread::spawn(move || {
+                render_tiles(&renderer, &tiles, &worker, &stealers, &pass_receiver, &results);
+            }));
+        }
+        TileScheduler { renderer, tiles, passes, results, threads }
+    }
+
+    // Whether a pixel still needs samples, which with adaptive sampling is until its error is
+    // below the threshold.
+    fn is_active(&self, stats: &PixelStats) -> bool {
+        let settings = &self.renderer.settings;
+        match settings.adaptive_threshold {
+            Some(threshold) => stats.count < settings.adaptive_min_samples || stats.relative_error() > threshold,
+            None => true,
+        }
+    }
+
+    // Renders another pass of `num_samples` samples for each active pixel, and folds them into
+    // `film`. Tiles with no active pixels are skipped.
+    pub fn accumulate(&self, film: &mut Film) -> RenderStats {
+        let start_time = Instant::now();
+        let active: Vec<bool> = film.pixels.iter().map(|stats| self.is_active(stats)).collect();
+        let active_pixels = active.iter().filter(|&&a| a).count();
+        let image_width = self.renderer.settings.image_width;
+        let pass = Arc::new(Pass { sample_counts: film.sample_counts(), active });
+
+        let jobs: Vec<Job> = self.tiles.iter().enumerate()
+            .filter(|&(_, tile)| {
+                (tile.y..(tile.y + tile.height)).any(|y| {
+                    let row = y * image_width + tile.x;
+                    pass.active[row..(row + tile.width)].contains(&true)
+                })
+            })
+            .map(|(tile, _)| Job { tile, pass: pass.clone() })
+            .collect();
+        let job_count = jobs.len();
+        let run_length = job_count.div_ceil(self.passes.len()).max(1);
+        for (sender, run) in self.passes.iter().zip(jobs.chunks(run_length)) {
+            sender.send(run.to_vec()).expect("Render thread exited");
+        }
+
+        let mut ray_count = 0;
+        for _ in 0..job_count {
+            let result = self.results.recv().expect("Render thread exited").unwrap_or_else(|panic| panic::resume_unwind(panic));
+            film.merge_tile(&self.tiles[result.tile], &result.stats, &result.aovs);
+            ray_count += result.ray_count;
+        }
+
+        let duration = Instant::now().duration_since(start_time);
+        RenderStats {
+            ray_count,
+            elapsed_time: duration.as_secs() as f64 + (duration.subsec_nanos() as f64 * 1e-9),
+            active_pixels,
+        }
+    }
+}
+
+impl Drop for TileScheduler {
+    fn drop(&mut self) {
+        // Closing the channels lets the threads finish
+        self.passes.clear();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn render_tiles(renderer: &Renderer, tiles: &[Tile], worker: &Worker<Job>, stealers: &[Stealer<Job>], passes: &Receiver<Vec<Job>>, results: &Sender<TileOutcome>) {
+    while let Ok(jobs) = passes.recv() {
+        // Pushed in reverse, as the thread takes from the end it pushes to
+        for job in jobs.into_iter().rev() {
+            worker.push(job);
+        }
+        while let Some(job) = worker.try_pop().or_else(|| steal(stealers)) {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                let tile = &tiles[job.tile];
+                let mut stats = vec![PixelStats::new(); tile.width * tile.height];
+                let mut aovs: Vec<AovBuffer> = renderer.settings.aovs.iter().map(|&aov| AovBuffer::new(aov, tile.width * tile.height)).collect();
+                let mut ray_count = 0;
+                renderer.render_tile(tile, &job.pass.sample_counts, &job.pass.active, &mut stats, &mut aovs, &mut ray_count);
+                TileResult { tile: job.tile, stats, aovs, ray_count }
+            }));
+            let panicked = outcome.is_err();
+            if results.send(outcome).is_err() || panicked {
+                return;
+            }
+        }
+    }
+}
+
+fn steal(stealers: &[Stealer<Job>]) -> Option<Job> {
+    loop {
+        let mut contended = false;
+        for stealer in stealers {
+            match stealer.steal() {
+                Steal::Data(job) => return Some(job),
+                Steal::Abort => contended = true,
+                Steal::Empty => {},
+            }
+        }
+        if !contended {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_exactly_once() {
+        let (width, height) = (67, 41);
+        for &order in &[TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let mut coverage = vec![0; width * height];
+            for tile in tiles(width, height, 16, order) {
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        coverage[y * width + x] += 1;
+                    }
+                }
+            }
+            assert!(coverage.iter().all(|&count| count == 1), "{:?} tiles don't cover each pixel once", order);
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use cgmath::*;
-use raytracing::{Hit, Hitable, Interval, Material, Ray};
+use raytracing::{Aabb, Hit, Hitable, Interval, Material, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+use raytracing::shapes::Transformed;
 use std::f32;
 
+// An axis-aligned box centred on the origin, see `Cuboid::new` to place one in the world.
 pub struct Cuboid {
-    pub transform: Matrix4<f32>,
     pub dimensions: Vector3<f32>,
     corner_min: Point3<f32>,
     corner_max: Point3<f32>,
@@ -11,49 +13,98 @@ pub struct Cuboid {
 }
 
 impl Cuboid {
-    pub fn new(transform: Matrix4<f32>, dimensions: Vector3<f32>, material: Material) -> Cuboid {
+    pub fn new(transform: Matrix4<f32>, dimensions: Vector3<f32>, material: Material) -> Transformed<Cuboid> {
+        Transformed::new(Cuboid::centred(dimensions, material), transform)
+    }
+
+    pub fn centred(dimensions: Vector3<f32>, material: Material) -> Cuboid {
         let half_dimensions = dimensions / 2.;
         let corner_min = Point3::from_vec(-half_dimensions);
-        let corner_max =Point3::from_vec( half_dimensions);
-        Cuboid { transform, dimensions, corner_min, corner_max, material }
+        let corner_max = Point3::from_vec(half_dimensions);
+        Cuboid { dimensions, corner_min, corner_max, material }
     }
 }
 
 impl Hitable for Cuboid {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit> {
-        let inverse_transform = self.transform.invert().unwrap();
-        let transformed_ray_origin = inverse_transform.transform_point(ray.origin);
-        let transformed_ray_direction = inverse_transform.transform_vector(ray.direction);
-
-        let vec1 = (self.corner_min - transformed_ray_origin).div_element_wise(transformed_ray_direction);
-        let vec2 = (self.corner_max - transformed_ray_origin).div_element_wise(transformed_ray_direction);
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
+        let vec1 = (self.corner_min - ray.origin).div_element_wise(ray.direction);
+        let vec2 = (self.corner_max - ray.origin).div_element_wise(ray.direction);
         let tmin = vec1.z.min(vec2.z).max(vec1.y.min(vec2.y).max(vec1.x.min(vec2.x)));
         let tmax = vec1.z.max(vec2.z).min(vec1.y.max(vec2.y).min(vec1.x.max(vec2.x)));
         let mut r = f32::INFINITY;
         if (tmax >= tmin) && (tmax >= 0.) {
-            let dist = if tmin >= 0. { tmin } else { tmax };
+            let dist = if tmin > interval.min { tmin } else { tmax };
             if (dist > interval.min) && (dist < interval.max) {
                 r = dist;
             }
         }
 
         if r < f32::INFINITY {
-            let location = transformed_ray_origin + (transformed_ray_direction * r);
-            //let t = (location - self.origin).div_element_wise(self.dimensions);
+            let location = ray.origin + (ray.direction * r);
+            // The face hit is the one along the axis where the location is furthest out, relative to the box size
             let t = location.to_vec().div_element_wise(self.dimensions);
             let normal = vec3(
-                if (t.x.abs() > t.y.abs()) && (t.z.abs() > t.z.abs()) { t.x.signum() } else { 0. },
-                if (t.y.abs() > t.x.abs()) && (t.z.abs() > t.z.abs()) { t.y.signum() } else { 0. },
-                if (t.z.abs() > t.x.abs()) && (t.z.abs() > t.y.abs()) { t.z.signum() } else { 0. },
+                if (t.x.abs() > t.y.abs()) && (t.x.abs() > t.z.abs()) { t.x.signum() } else { 0. },
+                if (t.y.abs() >= t.x.abs()) && (t.y.abs() > t.z.abs()) { t.y.signum() } else { 0. },
+                if (t.z.abs() >= t.x.abs()) && (t.z.abs() >= t.y.abs()) { t.z.signum() } else { 0. },
             );
-        
+
+            // Map each face onto the unit square using the other two axes
+            let f = (location - self.corner_min).div_element_wise(self.dimensions);
+            let uv = if normal.x != 0. {
+                Point2::new(f.z, f.y)
+            } else if normal.y != 0. {
+                Point2::new(f.x, f.z)
+            } else {
+                Point2::new(f.x, f.y)
+            };
+
             return Some(Hit {
                 distance: r,
-                location: self.transform.transform_point(location),
-                normal: self.transform.transform_vector(normal),
+                location,
+                normal,
+                geometric_normal: normal,
                 material: &*self.material,
+                uv,
             });
         }
         None
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb { min: self.corner_min, max: self.corner_max })
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitting()
+    }
+
+    fn area(&self) -> f32 {
+        let d = self.dimensions;
+        2. * (d.x * d.y + d.x * d.z + d.y * d.z)
+    }
+
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        // Pick a face in proportion to its area (what's left of the number picking the axis then
+        // picks the side), then a point on it
+        let d = self.dimensions;
+        let face_areas = [d.y * d.z, d.x * d.z, d.x * d.y];
+        let mut r = sampler.get_1d() * (face_areas[0] + face_areas[1] + face_areas[2]);
+        let mut axis = 0;
+        while axis < 2 && r >= face_areas[axis] {
+            r -= face_areas[axis];
+            axis += 1;
+        }
+        let side = if r < face_areas[axis] / 2. { -1. } else { 1. };
+
+        let u = sampler.get_2d();
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut location = Point3::origin();
+        location[axis] = side * d[axis] / 2.;
+        location[a] = (u.x - 0.5) * d[a];
+        location[b] = (u.y - 0.5) * d[b];
+        let mut normal = Vector3::zero();
+        normal[axis] = side;
+        Some(ShapeSample::new(origin, location, normal, 1. / self.area()))
+    }
+}
@@ -9,4 +9,11 @@ pub use self::rect_xz::RectXZ;
 mod rect_yz;
 pub use self::rect_yz::RectYZ;
 mod sphere;
-pub use self::sphere::Sphere;
\ No newline at end of file
+pub use self::sphere::Sphere;
+mod transformed;
+pub use self::transformed::Transformed;
+mod triangle;
+pub use self::triangle::{Mesh, Triangle, TriangleMesh};
+
+// Rects are infinitely thin, so pad their bounding boxes to keep them from being degenerate.
+const RECT_THICKNESS: f32 = 0.0001;
@@ -1,25 +1,39 @@
 use cgmath::*;
-use raytracing::{Hit, Hitable, Interval, Ray, ScatteringAndEmitting};
+use raytracing::{Hit, Hitable, Interval, Material, Ray};
 
 pub struct Plane {
     pub origin: Point3<f32>,
     pub normal: Vector3<f32>,
-    pub material: Box<ScatteringAndEmitting+Sync>,
+    pub material: Material,
+}
+
+impl Plane {
+    // Planar mapping, one unit in UV space per unit along two axes in the plane
+    fn uv(&self, location: Point3<f32>) -> Point2<f32> {
+        let axis = if self.normal.x.abs() > 0.9 { vec3(0., 1., 0.) } else { vec3(1., 0., 0.) };
+        let tangent = axis.cross(self.normal).normalize();
+        let bitangent = self.normal.cross(tangent);
+        let offset = location - self.origin;
+        Point2::new(dot(offset, tangent), dot(offset, bitangent))
+    }
 }
 
 impl Hitable for Plane {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
         if dot(ray.direction, self.normal) < 0. {
             let numerator = dot(self.normal, self.origin - ray.origin);
             let denominator = dot(self.normal, ray.direction);
             let r = numerator / denominator;
 
             if (r > interval.min) && (r < interval.max) && (denominator != 0.) {
+                let location = ray.origin + (ray.direction * r);
                 return Some(Hit {
                     distance: r,
-                    location: ray.origin + (ray.direction * r),
+                    location,
                     normal: self.normal,
+                    geometric_normal: self.normal,
                     material: &*self.material,
+                    uv: self.uv(location),
                 });
             }
         }
@@ -1,5 +1,7 @@
 use cgmath::*;
-use raytracing::{Hit, Hitable, Interval, Ray, ScatteringAndEmitting};
+use raytracing::{Aabb, Hit, Hitable, Interval, Material, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+use raytracing::shapes::RECT_THICKNESS;
 
 pub struct RectXY {
 	pub x0: f32,
@@ -7,11 +9,11 @@ pub struct RectXY {
 	pub y0: f32,
 	pub y1: f32,
 	pub k: f32,
-    pub material: Box<ScatteringAndEmitting+Sync>,
+    pub material: Material,
 }
 
 impl Hitable for RectXY {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
 		let ray_z_to_k = self.k - ray.origin.z;
 		let t = ray_z_to_k / ray.direction.z;
 		if (t < interval.min) || (t > interval.max) {
@@ -22,12 +24,33 @@ impl Hitable for RectXY {
 		if (x < self.x0) || (x > self.x1) || (y < self.y0) || (y > self.y1) {
 			return None;
 		}
+		let normal = vec3(0., 0., -ray_z_to_k.signum());
 		Some(Hit {
 			distance: t,
 			location: Point3::new(x, y, self.k),
-			normal: vec3(0., 0., -ray_z_to_k.signum()),
+			normal,
+			geometric_normal: normal,
 			material: &*self.material,
-			// uv: vec2((x - self.x0) / (self.x1 - self.x0), (y - self.y0) / (self.y1 - self.y0)),
+			uv: Point2::new((x - self.x0) / (self.x1 - self.x0), (y - self.y0) / (self.y1 - self.y0)),
 		})
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb { min: Point3::new(self.x0, self.y0, self.k - RECT_THICKNESS), max: Point3::new(self.x1, self.y1, self.k + RECT_THICKNESS) })
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitting()
+    }
+
+    fn area(&self) -> f32 {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        let u = sampler.get_2d();
+        let x = self.x0 + u.x * (self.x1 - self.x0);
+        let y = self.y0 + u.y * (self.y1 - self.y0);
+        Some(ShapeSample::new(origin, Point3::new(x, y, self.k), vec3(0., 0., 1.), 1. / self.area()))
+    }
+}
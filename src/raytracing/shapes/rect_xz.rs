@@ -1,5 +1,7 @@
 use cgmath::*;
-use raytracing::{Hit, Hitable, Interval, Ray, ScatteringAndEmitting};
+use raytracing::{Aabb, Hit, Hitable, Interval, Material, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+use raytracing::shapes::RECT_THICKNESS;
 
 pub struct RectXZ {
 	pub x0: f32,
@@ -7,11 +9,11 @@ pub struct RectXZ {
 	pub z0: f32,
 	pub z1: f32,
 	pub k: f32,
-    pub material: Box<ScatteringAndEmitting+Sync>,
+    pub material: Material,
 }
 
 impl Hitable for RectXZ {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
 		let ray_y_to_k = self.k - ray.origin.y;
 		let t = ray_y_to_k / ray.direction.y;
 		if (t < interval.min) || (t > interval.max) {
@@ -22,12 +24,33 @@ impl Hitable for RectXZ {
 		if (x < self.x0) || (x > self.x1) || (z < self.z0) || (z > self.z1) {
 			return None;
 		}
+		let normal = vec3(0., -ray_y_to_k.signum(), 0.);
 		Some(Hit {
 			distance: t,
 			location: Point3::new(x, self.k, z),
-			normal: vec3(0., -ray_y_to_k.signum(), 0.),
+			normal,
+			geometric_normal: normal,
 			material: &*self.material,
-			// uv: vec2((x - self.x0) / (self.x1 - self.x0), (z - self.z0) / (self.z1 - self.z0)),
+			uv: Point2::new((x - self.x0) / (self.x1 - self.x0), (z - self.z0) / (self.z1 - self.z0)),
 		})
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb { min: Point3::new(self.x0, self.k - RECT_THICKNESS, self.z0), max: Point3::new(self.x1, self.k + RECT_THICKNESS, self.z1) })
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitting()
+    }
+
+    fn area(&self) -> f32 {
+        (self.x1 - self.x0) * (self.z1 - self.z0)
+    }
+
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        let u = sampler.get_2d();
+        let x = self.x0 + u.x * (self.x1 - self.x0);
+        let z = self.z0 + u.y * (self.z1 - self.z0);
+        Some(ShapeSample::new(origin, Point3::new(x, self.k, z), vec3(0., 1., 0.), 1. / self.area()))
+    }
+}
@@ -1,5 +1,7 @@
 use cgmath::*;
-use raytracing::{Hit, Hitable, Interval, Ray, ScatteringAndEmitting};
+use raytracing::{Aabb, Hit, Hitable, Interval, Material, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+use raytracing::shapes::RECT_THICKNESS;
 
 pub struct RectYZ {
 	pub y0: f32,
@@ -7,11 +9,11 @@ pub struct RectYZ {
 	pub z0: f32,
 	pub z1: f32,
 	pub k: f32,
-    pub material: Box<ScatteringAndEmitting+Sync>,
+    pub material: Material,
 }
 
 impl Hitable for RectYZ {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
 		let ray_x_to_k = self.k - ray.origin.x;
 		let t = ray_x_to_k / ray.direction.x;
 		if (t < interval.min) || (t > interval.max) {
@@ -22,12 +24,33 @@ impl Hitable for RectYZ {
 		if (y < self.y0) || (y > self.y1) || (z < self.z0) || (z > self.z1) {
 			return None;
 		}
+		let normal = vec3(-ray_x_to_k.signum(), 0., 0.);
 		Some(Hit {
 			distance: t,
 			location: Point3::new(self.k, y, z),
-			normal: vec3(-ray_x_to_k.signum(), 0., 0.),
+			normal,
+			geometric_normal: normal,
 			material: &*self.material,
-			// uv: vec2((y - self.y0) / (self.y1 - self.y0), (z - self.z0) / (self.z1 - self.z0)),
+			uv: Point2::new((y - self.y0) / (self.y1 - self.y0), (z - self.z0) / (self.z1 - self.z0)),
 		})
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb { min: Point3::new(self.k - RECT_THICKNESS, self.y0, self.z0), max: Point3::new(self.k + RECT_THICKNESS, self.y1, self.z1) })
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitting()
+    }
+
+    fn area(&self) -> f32 {
+        (self.y1 - self.y0) * (self.z1 - self.z0)
+    }
+
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        let u = sampler.get_2d();
+        let y = self.y0 + u.x * (self.y1 - self.y0);
+        let z = self.z0 + u.y * (self.z1 - self.z0);
+        Some(ShapeSample::new(origin, Point3::new(self.k, y, z), vec3(1., 0., 0.), 1. / self.area()))
+    }
+}
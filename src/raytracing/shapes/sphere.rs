@@ -1,14 +1,38 @@
 use cgmath::*;
-use raytracing::{Hit, Hitable, Interval, Ray, ScatteringAndEmitting};
+use raytracing::{Aabb, Hit, Hitable, Interval, Material, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+use raytracing::util::{maths, random};
+use std::f32;
 
 pub struct Sphere {
     pub origin: Point3<f32>,
     pub radius: f32,
-    pub material: Box<ScatteringAndEmitting+Sync>,
+    pub material: Material,
+}
+
+impl Sphere {
+    // Longitude/latitude mapping, with u running around the Y axis and v from the bottom pole
+    fn uv(&self, location: Point3<f32>) -> Point2<f32> {
+        let p = (location - self.origin) / self.radius;
+        let phi = p.z.atan2(p.x);
+        let theta = p.y.clamp(-1., 1.).asin();
+        Point2::new(1. - (phi + f32::consts::PI) / (2. * f32::consts::PI), (theta + f32::consts::FRAC_PI_2) / f32::consts::PI)
+    }
+
+    // Cosine of the half angle of the cone the sphere subtends from `origin`, or None if inside it
+    fn cos_theta_max(&self, origin: &Point3<f32>) -> Option<f32> {
+        let radius2 = self.radius * self.radius;
+        let distance2 = (self.origin - origin).magnitude2();
+        if distance2 > radius2 {
+            Some((1. - radius2 / distance2).sqrt())
+        } else {
+            None
+        }
+    }
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
         let sphere_to_ray_origin = ray.origin - self.origin;
         let a = dot(ray.direction, ray.direction);
         let b = dot(sphere_to_ray_origin, ray.direction);
@@ -19,24 +43,83 @@ impl Hitable for Sphere {
             let tmp = (-b - (b * b - a * c).sqrt()) / a;
             if tmp < interval.max && tmp > interval.min {
                 let hit_location = ray.origin + (tmp * ray.direction);
+                let normal = (hit_location - self.origin) / self.radius;
                 return Some(Hit {
                     distance: tmp,
                     location: hit_location,
-                    normal: (hit_location - self.origin) / self.radius,
+                    normal,
+                    geometric_normal: normal,
                     material: &*self.material,
+                    uv: self.uv(hit_location),
                 });
             }
             let tmp = (-b + (b * b - a * c).sqrt()) / a;
             if tmp < interval.max && tmp > interval.min {
                 let hit_location = ray.origin + (tmp * ray.direction);
+                let normal = (hit_location - self.origin) / self.radius;
                 return Some(Hit {
                     distance: tmp,
                     location: hit_location,
-                    normal: (hit_location - self.origin) / self.radius,
+                    normal,
+                    geometric_normal: normal,
                     material: &*self.material,
+                    uv: self.uv(hit_location),
                 });
             }
         }
         None
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = vec3(self.radius, self.radius, self.radius).map(f32::abs);
+        Some(Aabb { min: self.origin - r, max: self.origin + r })
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emitting()
+    }
+
+    fn area(&self) -> f32 {
+        4. * f32::consts::PI * self.radius * self.radius
+    }
+
+    // From outside, only the cone of directions towards the sphere is sampled (so no samples are
+    // wasted on the far side), otherwise the whole surface is sampled uniformly.
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        let radius = self.radius.abs();
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => {
+                let normal = random::random_unit_vector(sampler);
+                return Some(ShapeSample::new(origin, self.origin + normal * radius, normal, 1. / self.area()));
+            },
+        };
+
+        let u = sampler.get_2d();
+        let cos_theta = 1. + u.x * (cos_theta_max - 1.);
+        let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+        let phi = 2. * f32::consts::PI * u.y;
+        let to_centre = self.origin - origin;
+        let distance = to_centre.magnitude();
+        let w = to_centre / distance;
+        let (u, v) = maths::orthonormal_basis(w);
+        let direction = (u * phi.cos() + v * phi.sin()) * sin_theta + w * cos_theta;
+
+        // Distance to the near side of the sphere along the sampled direction
+        let t = distance * cos_theta - (radius * radius - distance * distance * sin_theta * sin_theta).max(0.).sqrt();
+        let location = origin + direction * t;
+        let normal = (location - self.origin) / radius;
+        Some(ShapeSample { location, normal, pdf: 1. / (2. * f32::consts::PI * (1. - cos_theta_max)) })
+    }
+
+    fn pdf(&self, origin: &Point3<f32>, direction: &Vector3<f32>) -> f32 {
+        let ray = Ray { origin: *origin, direction: *direction, wavelength: None };
+        match self.hit(&ray, &Interval { min: 0.001, max: f32::MAX }) {
+            Some(hit) => match self.cos_theta_max(origin) {
+                Some(cos_theta_max) => 1. / (2. * f32::consts::PI * (1. - cos_theta_max)),
+                None => maths::area_to_solid_angle_pdf(1. / self.area(), hit.distance, dot(*direction, hit.geometric_normal)),
+            },
+            None => 0.,
+        }
+    }
+}
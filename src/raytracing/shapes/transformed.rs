@@ -0,0 +1,85 @@
+use cgmath::*;
+use raytracing::{Aabb, Hit, Hitable, Interval, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+
+// Places any shape in the world with an affine transform. The shape is intersected in its own
+// space, so it can be rotated, scaled or (wrapping an `Arc`) instanced many times.
+pub struct Transformed<H: Hitable> {
+    pub shape: H,
+    transform: Matrix4<f32>,
+    inverse_transform: Matrix4<f32>,
+    // Normals transform by the inverse transpose so they stay perpendicular under non-uniform scale
+    normal_matrix: Matrix3<f32>,
+    determinant: f32,
+}
+
+impl<H: Hitable> Transformed<H> {
+    pub fn new(shape: H, transform: Matrix4<f32>) -> Transformed<H> {
+        let inverse_transform = transform.invert().expect("Shape transform must be invertible");
+        let normal_matrix = Matrix3::from_cols(
+            inverse_transform.x.truncate(),
+            inverse_transform.y.truncate(),
+            inverse_transform.z.truncate(),
+        ).transpose();
+        let determinant = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate()).determinant();
+        Transformed { shape, transform, inverse_transform, normal_matrix, determinant }
+    }
+
+    pub fn transform(&self) -> &Matrix4<f32> {
+        &self.transform
+    }
+
+    // Converts a solid angle pdf for the direction `local_direction` (a unit vector in the shape's
+    // space) to world space. The transform maps directions with Jacobian |det M| / |M w|^3, so this
+    // holds under any affine transform, unlike scaling the area.
+    fn to_world_pdf(&self, local_pdf: f32, local_direction: Vector3<f32>) -> f32 {
+        let scale = self.transform.transform_vector(local_direction).magnitude();
+        local_pdf * scale * scale * scale / self.determinant.abs()
+    }
+}
+
+impl<H: Hitable> Hitable for Transformed<H> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
+        // The direction isn't renormalised, so distances along the ray are the same in both spaces
+        let local_ray = Ray {
+            origin: self.inverse_transform.transform_point(ray.origin),
+            direction: self.inverse_transform.transform_vector(ray.direction),
+            wavelength: ray.wavelength,
+        };
+        self.shape.hit(&local_ray, interval).map(|hit| Hit {
+            location: self.transform.transform_point(hit.location),
+            normal: (self.normal_matrix * hit.normal).normalize(),
+            geometric_normal: (self.normal_matrix * hit.geometric_normal).normalize(),
+            ..hit
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.shape.bounding_box().map(|bounds| {
+            let corners: Vec<Point3<f32>> = bounds.corners().iter().map(|&c| self.transform.transform_point(c)).collect();
+            Aabb::from_points(&corners)
+        })
+    }
+
+    fn is_light(&self) -> bool {
+        self.shape.is_light()
+    }
+
+    // Area isn't simply scaled under a non-uniform scale, so `area` is left unimplemented and the
+    // pdfs are converted per direction instead.
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        let local_origin = self.inverse_transform.transform_point(*origin);
+        let local = self.shape.sample(&local_origin, sampler)?;
+        Some(ShapeSample {
+            location: self.transform.transform_point(local.location),
+            normal: (self.normal_matrix * local.normal).normalize(),
+            pdf: self.to_world_pdf(local.pdf, (local.location - local_origin).normalize()),
+        })
+    }
+
+    fn pdf(&self, origin: &Point3<f32>, direction: &Vector3<f32>) -> f32 {
+        let local_origin = self.inverse_transform.transform_point(*origin);
+        let local_direction = self.inverse_transform.transform_vector(*direction).normalize();
+        self.to_world_pdf(self.shape.pdf(&local_origin, &local_direction), local_direction)
+    }
+}
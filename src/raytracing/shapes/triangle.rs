@@ -0,0 +1,211 @@
+use cgmath::*;
+use raytracing::acceleration::{Bvh};
+use raytracing::{Aabb, Hit, Hitable, HitableCollection, Interval, Material, Ray, ShapeSample};
+use raytracing::samplers::Sampler;
+use std::sync::Arc;
+
+// Vertex buffers shared by all of the triangles of a mesh. Normals and UVs are optional, but if
+// present there must be one per position.
+pub struct Mesh {
+    pub positions: Vec<Point3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<Point2<f32>>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Material,
+}
+
+pub struct Triangle {
+    pub mesh: Arc<Mesh>,
+    pub index: usize,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [usize; 3] {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        [i0 as usize, i1 as usize, i2 as usize]
+    }
+
+    fn max_dimension(v: Vector3<f32>) -> usize {
+        if v.x > v.y && v.x > v.z { 0 } else if v.y > v.z { 1 } else { 2 }
+    }
+
+    fn permute(v: Vector3<f32>, kx: usize, ky: usize, kz: usize) -> Vector3<f32> {
+        vec3(v[kx], v[ky], v[kz])
+    }
+}
+
+impl Hitable for Triangle {
+    // Watertight ray-triangle intersection (Woop, Benthin & Wald 2013): the vertices are
+    // transformed into a space where the ray runs down +z from the origin, so rays through shared
+    // edges and vertices can't slip between neighbouring triangles.
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
+        let mesh = &*self.mesh;
+        let [i0, i1, i2] = self.vertices();
+        let (p0, p1, p2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+
+        let kz = Triangle::max_dimension(ray.direction.map(f32::abs));
+        let kx = (kz + 1) % 3;
+        let ky = (kx + 1) % 3;
+        let d = Triangle::permute(ray.direction, kx, ky, kz);
+        let mut p0t = Triangle::permute(p0 - ray.origin, kx, ky, kz);
+        let mut p1t = Triangle::permute(p1 - ray.origin, kx, ky, kz);
+        let mut p2t = Triangle::permute(p2 - ray.origin, kx, ky, kz);
+
+        // Shear so the ray direction lies along +z
+        let sx = -d.x / d.z;
+        let sy = -d.y / d.z;
+        let sz = 1. / d.z;
+        p0t.x += sx * p0t.z;
+        p0t.y += sy * p0t.z;
+        p1t.x += sx * p1t.z;
+        p1t.y += sy * p1t.z;
+        p2t.x += sx * p2t.z;
+        p2t.y += sy * p2t.z;
+
+        let mut e0 = p1t.x * p2t.y - p1t.y * p2t.x;
+        let mut e1 = p2t.x * p0t.y - p2t.y * p0t.x;
+        let mut e2 = p0t.x * p1t.y - p0t.y * p1t.x;
+        // Fall back to double precision when the ray passes exactly through an edge
+        if e0 == 0. || e1 == 0. || e2 == 0. {
+            e0 = ((p1t.x as f64) * (p2t.y as f64) - (p1t.y as f64) * (p2t.x as f64)) as f32;
+            e1 = ((p2t.x as f64) * (p0t.y as f64) - (p2t.y as f64) * (p0t.x as f64)) as f32;
+            e2 = ((p0t.x as f64) * (p1t.y as f64) - (p0t.y as f64) * (p1t.x as f64)) as f32;
+        }
+        if (e0 < 0. || e1 < 0. || e2 < 0.) && (e0 > 0. || e1 > 0. || e2 > 0.) {
+            return None;
+        }
+        let det = e0 + e1 + e2;
+        if det == 0. {
+            return None;
+        }
+
+        p0t.z *= sz;
+        p1t.z *= sz;
+        p2t.z *= sz;
+        let t_scaled = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
+        if det < 0. && (t_scaled >= interval.min * det || t_scaled <= interval.max * det) {
+            return None;
+        }
+        if det > 0. && (t_scaled <= interval.min * det || t_scaled >= interval.max * det) {
+            return None;
+        }
+
+        let inv_det = 1. / det;
+        let (b0, b1, b2) = (e0 * inv_det, e1 * inv_det, e2 * inv_det);
+        let distance = t_scaled * inv_det;
+
+        let geometric_normal = (p1 - p0).cross(p2 - p0).normalize();
+        let normal = if mesh.normals.is_empty() {
+            geometric_normal
+        } else {
+            let shading_normal = (mesh.normals[i0] * b0 + mesh.normals[i1] * b1 + mesh.normals[i2] * b2).normalize();
+            if shading_normal.x.is_finite() { shading_normal } else { geometric_normal }
+        };
+
+        let uv = if mesh.uvs.is_empty() {
+            Point2::new(b1, b2)
+        } else {
+            Point2::from_vec(mesh.uvs[i0].to_vec() * b0 + mesh.uvs[i1].to_vec() * b1 + mesh.uvs[i2].to_vec() * b2)
+        };
+
+        Some(Hit {
+            distance,
+            location: Point3::from_vec(p0.to_vec() * b0 + p1.to_vec() * b1 + p2.to_vec() * b2),
+            normal,
+            geometric_normal,
+            material: &*mesh.material,
+            uv,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        Some(Aabb::from_points(&[positions[i0], positions[i1], positions[i2]]))
+    }
+
+    fn is_light(&self) -> bool {
+        self.mesh.material.is_emitting()
+    }
+
+    fn area(&self) -> f32 {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        0.5 * (positions[i1] - positions[i0]).cross(positions[i2] - positions[i0]).magnitude()
+    }
+
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+        // Uniform barycentrics, folding the square onto the triangle with a square root
+        let u = sampler.get_2d();
+        let su = u.x.sqrt();
+        let b0 = 1. - su;
+        let b1 = u.y * su;
+        let b2 = 1. - b0 - b1;
+        let location = Point3::from_vec(positions[i0].to_vec() * b0 + positions[i1].to_vec() * b1 + positions[i2].to_vec() * b2);
+        let normal = (positions[i1] - positions[i0]).cross(positions[i2] - positions[i0]).normalize();
+        Some(ShapeSample::new(origin, location, normal, 1. / self.area()))
+    }
+}
+
+// A mesh with its own BVH over its triangles, so it can be added to a scene (or instanced) as a
+// single shape.
+pub struct TriangleMesh {
+    pub mesh: Arc<Mesh>,
+    bvh: Bvh,
+    // Running total of the triangle areas, for picking a triangle to sample
+    area_cdf: Vec<f32>,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: Mesh) -> TriangleMesh {
+        let mesh = Arc::new(mesh);
+        let bvh = Bvh::new(TriangleMesh::triangles(&mesh));
+        let mut total_area = 0.;
+        let area_cdf = (0..mesh.indices.len()).map(|index| {
+            total_area += Triangle { mesh: mesh.clone(), index }.area();
+            total_area
+        }).collect();
+        TriangleMesh { mesh, bvh, area_cdf }
+    }
+
+    pub fn triangles(mesh: &Arc<Mesh>) -> HitableCollection {
+        let mut triangles: HitableCollection = Vec::with_capacity(mesh.indices.len());
+        for index in 0..mesh.indices.len() {
+            triangles.push(Box::new(Triangle { mesh: mesh.clone(), index }));
+        }
+        triangles
+    }
+}
+
+impl Hitable for TriangleMesh {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
+        self.bvh.hit(ray, interval)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    fn is_light(&self) -> bool {
+        self.mesh.material.is_emitting() && self.area() > 0.
+    }
+
+    fn area(&self) -> f32 {
+        self.area_cdf.last().cloned().unwrap_or(0.)
+    }
+
+    // Uniform over the whole surface, so the default `pdf` applies
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        if self.area_cdf.is_empty() {
+            return None;
+        }
+        let r = sampler.get_1d() * self.area();
+        let index = match self.area_cdf.binary_search_by(|a| a.partial_cmp(&r).unwrap()) {
+            Ok(index) | Err(index) => index.min(self.area_cdf.len() - 1),
+        };
+        let sample = Triangle { mesh: self.mesh.clone(), index }.sample(origin, sampler)?;
+        Some(ShapeSample::new(origin, sample.location, sample.normal, 1. / self.area()))
+    }
+}
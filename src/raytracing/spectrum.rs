@@ -0,0 +1,134 @@
+use cgmath::*;
+use std::f32;
+
+// Wavelengths (in nm) that spectral rendering samples between
+pub const MIN_WAVELENGTH: f32 = 380.;
+pub const MAX_WAVELENGTH: f32 = 780.;
+// Wavelengths each path carries
+pub const HERO_WAVELENGTHS: usize = 4;
+
+// Values at each of a path's wavelengths in spectral mode, or red, green and blue (with the last
+// unused) otherwise.
+pub type Spectrum = Vector4<f32>;
+
+// What the colours of a path are made of. In spectral mode a path carries the hero wavelength it was
+// given and others spaced evenly from it across the visible range (Wilkie et al. 2014), so each
+// path still contributes to every colour. RGB colours from the scene are turned into spectra where
+// they meet the path, and the radiance the path brings back is turned back into RGB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathColours {
+    Rgb,
+    Spectral([f32; HERO_WAVELENGTHS]),
+}
+
+impl PathColours {
+    // Wavelengths for a path from a number in [0, 1).
+    pub fn sample_wavelengths(u: f32) -> PathColours {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let mut wavelengths = [0.; HERO_WAVELENGTHS];
+        for (i, wavelength) in wavelengths.iter_mut().enumerate() {
+            let offset = (u + i as f32 / HERO_WAVELENGTHS as f32).fract();
+            *wavelength = MIN_WAVELENGTH + offset * range;
+        }
+        PathColours::Spectral(wavelengths)
+    }
+
+    // The wavelength that the path's direction is picked for, in spectral mode.
+    pub fn hero_wavelength(&self) -> Option<f32> {
+        match *self {
+            PathColours::Rgb => None,
+            PathColours::Spectral(wavelengths) => Some(wavelengths[0]),
+        }
+    }
+
+    // Drops the other wavelengths from a path that only the hero can follow, scaling it to make up
+    // for them so each still counts the same on average.
+    pub fn hero_only(&self, spectrum: Spectrum) -> Spectrum {
+        match *self {
+            PathColours::Rgb => spectrum,
+            PathColours::Spectral(_) => vec4(spectrum.x * HERO_WAVELENGTHS as f32, 0., 0., 0.),
+        }
+    }
+
+    // An RGB colour (a reflectance or an emission) at the path's wavelengths.
+    pub fn from_rgb(&self, rgb: Vector3<f32>) -> Spectrum {
+        match *self {
+            PathColours::Rgb => rgb.extend(0.),
+            PathColours::Spectral(wavelengths) => {
+                vec4(rgb_to_spectrum(rgb, wavelengths[0]), rgb_to_spectrum(rgb, wavelengths[1]),
+                    rgb_to_spectrum(rgb, wavelengths[2]), rgb_to_spectrum(rgb, wavelengths[3]))
+            },
+        }
+    }
+
+    pub fn to_rgb(&self, spectrum: Spectrum) -> Vector3<f32> {
+        match *self {
+            PathColours::Rgb => spectrum.truncate(),
+            PathColours::Spectral(wavelengths) => {
+                // Monte Carlo estimate of the integral against the matching functions, with the
+                // wavelengths picked uniformly
+                let mut xyz = Vector3::zero();
+                for (i, &wavelength) in wavelengths.iter().enumerate() {
+                    xyz += colour_matching(wavelength) * spectrum[i];
+                }
+                xyz *= (MAX_WAVELENGTH - MIN_WAVELENGTH) / HERO_WAVELENGTHS as f32;
+                xyz_to_rgb(xyz).div_element_wise(xyz_to_rgb(CONSTANT_SPECTRUM_XYZ))
+            },
+        }
+    }
+}
+
+// XYZ of a spectrum of one at every wavelength, which comes out white (so the white point is E,
+// rather than sRGB's D65)
+const CONSTANT_SPECTRUM_XYZ: Vector3<f32> = Vector3 { x: 106.765, y: 106.920, z: 106.825 };
+
+// Wyman, Sloan and Shirley's multi-lobe Gaussian fit of the CIE 1931 colour matching functions.
+fn colour_matching(wavelength: f32) -> Vector3<f32> {
+    let gaussian = |mean: f32, below: f32, above: f32| {
+        let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    vec3(
+        1.056 * gaussian(599.8, 37.9, 31.0) + 0.362 * gaussian(442.0, 16.0, 26.7) - 0.065 * gaussian(501.1, 20.4, 26.2),
+        0.821 * gaussian(568.8, 46.9, 40.5) + 0.286 * gaussian(530.9, 16.3, 31.1),
+        1.217 * gaussian(437.0, 11.8, 36.0) + 0.681 * gaussian(459.0, 26.0, 13.8),
+    )
+}
+
+// To linear sRGB
+fn xyz_to_rgb(xyz: Vector3<f32>) -> Vector3<f32> {
+    vec3(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+// Smits' basis spectra, in 10 bins from 380 to 720nm (the last reaching on to the end of the range)
+const SMITS_BIN_WIDTH: f32 = 34.;
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+// Smits' RGB to spectrum conversion (1999): as much white as all three channels share, then as
+// much of the secondary colour that the two largest share, then the primary for the rest. Colours
+// within [0, 1] give reflectances within it too, and the spectrum scales with the colour, so it
+// serves for emission as well.
+fn rgb_to_spectrum(rgb: Vector3<f32>, wavelength: f32) -> f32 {
+    let bin = (((wavelength - MIN_WAVELENGTH) / SMITS_BIN_WIDTH) as usize).min(9);
+    let (r, g, b) = (rgb.x.max(0.), rgb.y.max(0.), rgb.z.max(0.));
+    if r <= g && r <= b {
+        let (secondary, primary) = if g <= b { (g - r, b - g) } else { (b - r, g - b) };
+        r * SMITS_WHITE[bin] + secondary * SMITS_CYAN[bin] + primary * if g <= b { SMITS_BLUE[bin] } else { SMITS_GREEN[bin] }
+    } else if g <= r && g <= b {
+        let (secondary, primary) = if r <= b { (r - g, b - r) } else { (b - g, r - b) };
+        g * SMITS_WHITE[bin] + secondary * SMITS_MAGENTA[bin] + primary * if r <= b { SMITS_BLUE[bin] } else { SMITS_RED[bin] }
+    } else {
+        let (secondary, primary) = if r <= g { (r - b, g - r) } else { (g - b, r - g) };
+        b * SMITS_WHITE[bin] + secondary * SMITS_YELLOW[bin] + primary * if r <= g { SMITS_GREEN[bin] } else { SMITS_RED[bin] }
+    }
+}
@@ -0,0 +1,22 @@
+use cgmath::*;
+use raytracing::{BoxedTexture, Texture};
+
+// A 3D checkerboard of cubes π/`scale` units across, laid out by the point in space rather than
+// the UV, so it carries on through solids whatever their mapping. Cells where an odd number of
+// sin(scale·x), sin(scale·y) and sin(scale·z) are negative show `odd`, the others `even`.
+pub struct CheckerTexture {
+    pub odd: BoxedTexture,
+    pub even: BoxedTexture,
+    pub scale: f32,
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f32, v: f32, p: &Point3<f32>) -> Vector3<f32> {
+        let sines = (self.scale * p.x).sin() * (self.scale * p.y).sin() * (self.scale * p.z).sin();
+        if sines < 0. {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+}
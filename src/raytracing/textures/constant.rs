@@ -0,0 +1,18 @@
+use cgmath::*;
+use raytracing::{BoxedTexture, Texture};
+
+pub struct ConstantTexture {
+    pub colour: Vector3<f32>,
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f32, _v: f32, _p: &Point3<f32>) -> Vector3<f32> {
+        self.colour
+    }
+}
+
+impl From<Vector3<f32>> for BoxedTexture {
+    fn from(colour: Vector3<f32>) -> BoxedTexture {
+        Box::new(ConstantTexture { colour })
+    }
+}
@@ -0,0 +1,41 @@
+use cgmath::*;
+use image;
+use raytracing::Texture;
+use std::path::Path;
+
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    // Linear RGB
+    pixels: Vec<Vector3<f32>>,
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl ImageTexture {
+    // Loads an 8-bit PNG/JPEG image, converting from sRGB to linear.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture, image::ImageError> {
+        let image = image::open(path)?.to_rgb();
+        let (width, height) = image.dimensions();
+        let pixels = image.pixels().map(|p| vec3(srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2]))).collect();
+        Ok(ImageTexture { width: width as usize, height: height as usize, pixels })
+    }
+}
+
+impl Texture for ImageTexture {
+    // Nearest-neighbour lookup, wrapping outside 0..1. v runs up the image.
+    fn value(&self, u: f32, v: f32, _p: &Point3<f32>) -> Vector3<f32> {
+        let u = u - u.floor();
+        let v = 1. - (v - v.floor());
+        let i = ((u * self.width as f32) as usize).min(self.width - 1);
+        let j = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+}
@@ -0,0 +1,11 @@
+mod checker;
+pub use self::checker::CheckerTexture;
+
+mod constant;
+pub use self::constant::ConstantTexture;
+
+mod image_texture;
+pub use self::image_texture::ImageTexture;
+
+mod noise;
+pub use self::noise::{NoiseTexture, NoiseType, Perlin};
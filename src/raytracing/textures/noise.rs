@@ -0,0 +1,103 @@
+use cgmath::*;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use raytracing::Texture;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise using random unit vectors on a lattice, as in "Ray Tracing: The Next Week".
+pub struct Perlin {
+    gradients: Vec<Vector3<f32>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    // Seeded so textures look the same from one render to the next.
+    pub fn new(seed: u32) -> Perlin {
+        let mut rng = XorShiftRng::from_seed([seed ^ 0x9e37_79b9, 0x243f_6a88, 0x85a3_08d3, 0x1319_8a2e]);
+        let gradients = (0..POINT_COUNT).map(|_| {
+            vec3(rng.gen::<f32>() * 2. - 1., rng.gen::<f32>() * 2. - 1., rng.gen::<f32>() * 2. - 1.).normalize()
+        }).collect();
+        let permutation = |rng: &mut XorShiftRng| {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            rng.shuffle(&mut p);
+            p
+        };
+        let perm_x = permutation(&mut rng);
+        let perm_y = permutation(&mut rng);
+        let perm_z = permutation(&mut rng);
+        Perlin { gradients, perm_x, perm_y, perm_z }
+    }
+
+    pub fn noise(&self, p: &Point3<f32>) -> f32 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+        let i = p.x.floor() as i32;
+        let j = p.y.floor() as i32;
+        let k = p.z.floor() as i32;
+
+        // Hermite smoothing avoids grid artifacts
+        let uu = u * u * (3. - 2. * u);
+        let vv = v * v * (3. - 2. * v);
+        let ww = w * w * (3. - 2. * w);
+
+        let mut accumulated = 0.;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let (fi, fj, fk) = (di as f32, dj as f32, dk as f32);
+                    let weight = vec3(u - fi, v - fj, w - fk);
+                    accumulated += (fi * uu + (1. - fi) * (1. - uu))
+                        * (fj * vv + (1. - fj) * (1. - vv))
+                        * (fk * ww + (1. - fk) * (1. - ww))
+                        * dot(self.gradients[index], weight);
+                }
+            }
+        }
+        accumulated
+    }
+
+    pub fn turbulence(&self, p: &Point3<f32>, depth: u32) -> f32 {
+        let mut accumulated = 0.;
+        let mut p = *p;
+        let mut weight = 1.;
+        for _ in 0..depth {
+            accumulated += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.;
+        }
+        accumulated.abs()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum NoiseType {
+    Perlin,
+    Turbulence,
+    Marble,
+}
+
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub noise_type: NoiseType,
+    pub colour: Vector3<f32>,
+    pub scale: f32,
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Point3<f32>) -> Vector3<f32> {
+        let p = *p * self.scale;
+        let intensity = match self.noise_type {
+            NoiseType::Perlin => 0.5 * (1. + self.noise.noise(&p)),
+            NoiseType::Turbulence => self.noise.turbulence(&p, 7),
+            // Phase shifted stripes give the veins
+            NoiseType::Marble => 0.5 * (1. + (p.z + 10. * self.noise.turbulence(&p, 7)).sin()),
+        };
+        self.colour * intensity
+    }
+}
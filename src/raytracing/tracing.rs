@@ -1,41 +1,322 @@
 use cgmath::*;
-use raytracing::{BoxedHitable, Hit, Interval, Ray};
+use raytracing::{Environment, Hit, Hitable, Interval, Ray, ScatteredRay};
+use raytracing::acceleration::Bvh;
+use raytracing::samplers::{self, Sampler};
+use raytracing::spectrum::{PathColours, Spectrum};
 use std::f32;
 
-pub fn hit<'a>(shapes: &'a[BoxedHitable], ray: &Ray, interval: &Interval) -> Option<Hit<'a>> {
-    let mut hit_result: Option<Hit> = None;
-    let mut closest = interval.max;
-    for shape in shapes
-    {
-        if let Some(hit) = shape.hit(ray, &Interval { min: interval.min, max: closest }) {
-            closest = hit.distance;
-            hit_result = Some(hit);
+// Relative tolerance for a shadow ray reaching the point sampled on a light
+const SHADOW_EPSILON: f32 = 0.001;
+
+// How long paths can get. Each kind of bounce is limited separately, so that e.g. light can pass
+// through many layers of glass even where a few diffuse bounces are enough. Russian roulette can
+// end paths once they've taken `roulette_depth` bounces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathLimits {
+    pub max_diffuse_depth: u32,
+    pub max_specular_depth: u32,
+    pub max_transmission_depth: u32,
+    pub roulette_depth: u32,
+}
+
+impl Default for PathLimits {
+    fn default() -> PathLimits {
+        PathLimits {
+            max_diffuse_depth: 50,
+            max_specular_depth: 50,
+            max_transmission_depth: 50,
+            roulette_depth: 3,
+        }
+    }
+}
+
+// Paths only go on with at most this probability, so even bright ones end eventually
+const MAX_SURVIVAL_PROBABILITY: f32 = 0.95;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BounceKind {
+    // Anything sampled from a distribution of directions, including glossy reflections
+    Diffuse,
+    // Mirror reflections
+    Specular,
+    // Refractions into or out of a surface
+    Transmission,
+}
+
+fn bounce_kind(ray: &Ray, hit: &Hit, scattered: &ScatteredRay) -> BounceKind {
+    if !scattered.specular {
+        BounceKind::Diffuse
+    } else if dot(ray.direction, hit.geometric_normal) * dot(scattered.ray.direction, hit.geometric_normal) > 0. {
+        BounceKind::Transmission
+    } else {
+        BounceKind::Specular
+    }
+}
+
+// How much of the light found further along a path makes it back to the camera, at each of its
+// colours, and the bounces of each kind it has taken so far.
+struct Path {
+    colours: PathColours,
+    throughput: Spectrum,
+    // Whether only the hero wavelength is left
+    dispersed: bool,
+    diffuse_bounces: u32,
+    specular_bounces: u32,
+    transmission_bounces: u32,
+}
+
+impl Path {
+    fn new(colours: PathColours) -> Path {
+        Path { colours, throughput: vec4(1., 1., 1., 1.), dispersed: false, diffuse_bounces: 0, specular_bounces: 0, transmission_bounces: 0 }
+    }
+
+    // Light (in RGB) found along the path, as it reaches the camera.
+    fn weight(&self, light: Vector3<f32>) -> Spectrum {
+        self.throughput.mul_element_wise(self.colours.from_rgb(light))
+    }
+
+    // Whether the path follows the scattered ray at bounce `depth`, weighting the throughput by its
+    // attenuation if so. It ends once it has taken too many bounces of the ray's kind, or at random
+    // with Russian roulette, with a chance of going on that follows the throughput so that paths
+    // are ended where they'd add little. Those that go on are boosted to make up for them.
+    fn continues(&mut self, limits: &PathLimits, ray: &Ray, hit: &Hit, scattered: &ScatteredRay, depth: u32, sampler: &mut dyn Sampler) -> bool {
+        let (bounces, max_depth) = match bounce_kind(ray, hit, scattered) {
+            BounceKind::Diffuse => (&mut self.diffuse_bounces, limits.max_diffuse_depth),
+            BounceKind::Specular => (&mut self.specular_bounces, limits.max_specular_depth),
+            BounceKind::Transmission => (&mut self.transmission_bounces, limits.max_transmission_depth),
+        };
+        *bounces += 1;
+        if *bounces > max_depth {
+            return false;
+        }
+        self.throughput.mul_assign_element_wise(self.colours.from_rgb(scattered.attenuation));
+        if scattered.dispersed && !self.dispersed {
+            self.throughput = self.colours.hero_only(self.throughput);
+            self.dispersed = true;
+        }
+
+        if depth + 1 < limits.roulette_depth {
+            return true;
+        }
+        let throughput = self.throughput;
+        let probability = throughput.x.max(throughput.y).max(throughput.z).max(throughput.w).min(MAX_SURVIVAL_PROBABILITY);
+        sampler.set_dimension(samplers::bounce_dimension(depth, samplers::ROULETTE_DIMENSION));
+        if probability <= 0. || sampler.get_1d() >= probability {
+            return false;
+        }
+        self.throughput /= probability;
+        true
+    }
+}
+
+pub fn trace(world: &dyn Hitable, environment: Option<&dyn Environment>, ray: &Ray, colours: PathColours, limits: &PathLimits, sampler: &mut dyn Sampler, ray_count: &mut u64) -> Vector3<f32> {
+    let mut radiance = Spectrum::zero();
+    let mut path = Path::new(colours);
+    let mut ray = *ray;
+    for depth in 0.. {
+        *ray_count += 1;
+        let hit = match world.hit(&ray, &Interval { min: 0.001, max: f32::MAX }) {
+            None => {
+                if let Some(environment) = environment {
+                    radiance += path.weight(environment.radiance(&ray.direction.normalize()));
+                }
+                break;
+            },
+            Some(hit) => hit,
+        };
+        radiance += path.weight(hit.material.emit(hit.uv.x, hit.uv.y, &hit.location));
+
+        sampler.set_dimension(samplers::bounce_dimension(depth, samplers::SCATTER_DIMENSIONS));
+        match hit.material.scatter(&ray, &hit, sampler) {
+            Some(scattered) if path.continues(limits, &ray, &hit, &scattered, depth, sampler) => ray = scattered.ray,
+            _ => break,
+        }
+    }
+    colours.to_rgb(radiance)
+}
+
+// Everything that's sampled for direct lighting: the shapes that emit light, each with its index in
+// the scene's shapes, and the environment.
+pub struct Lights<'a> {
+    pub shapes: Vec<(usize, &'a dyn Hitable)>,
+    pub environment: Option<&'a dyn Environment>,
+}
+
+impl<'a> Lights<'a> {
+    fn count(&self) -> usize {
+        self.shapes.len() + self.environment.iter().count()
+    }
+
+    // Solid angle pdf of `sample` picking the point on the shape at `index` in the scene seen from
+    // `origin` along the unit vector `direction`. Zero if that shape isn't a light.
+    pub fn pdf(&self, index: usize, origin: &Point3<f32>, direction: &Vector3<f32>) -> f32 {
+        match self.shapes.iter().find(|&&(light_index, _)| light_index == index) {
+            Some(&(_, light)) => light.pdf(origin, direction) / self.count() as f32,
+            None => 0.,
         }
     }
-    hit_result
+
+    // Solid angle pdf of `sample` picking the unit vector `direction` towards the environment.
+    pub fn environment_pdf(&self, direction: &Vector3<f32>) -> f32 {
+        match self.environment {
+            Some(environment) => environment.pdf(direction) / self.count() as f32,
+            None => 0.,
+        }
+    }
+
+    // Picks a light uniformly and samples a point on it, returning the direction and distance to
+    // it along with the pdf of choosing that direction. The environment is infinitely far away.
+    pub fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<(Vector3<f32>, f32, f32)> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let i = ((sampler.get_1d() * count as f32) as usize).min(count - 1);
+        if i == self.shapes.len() {
+            let (direction, pdf) = self.environment?.sample(sampler)?;
+            if pdf <= 0. {
+                return None;
+            }
+            return Some((direction, f32::INFINITY, pdf / count as f32));
+        }
+        let sample = self.shapes[i].1.sample(origin, sampler)?;
+        let to_light = sample.location - origin;
+        let distance = to_light.magnitude();
+        if sample.pdf <= 0. || distance <= 0. {
+            return None;
+        }
+        Some((to_light / distance, distance, sample.pdf / count as f32))
+    }
+}
+
+// Balances the pdfs of two sampling strategies, weighting towards whichever is more likely to
+// pick the direction (Veach's power heuristic with a power of two).
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. { a / (a + b) } else { 0. }
 }
 
-pub fn trace(shapes: &[BoxedHitable], ray: &Ray, depth: u32, ray_count: &mut u64) -> Vector3<f32> {
+// Light arriving at `hit` directly from a sampled point on one of the lights (or direction of the
+// environment), and the reflectance that takes it back along `ray`, kept apart so that they can
+// each be turned into spectra. The shadow ray is traced into the world, so the emission is that of
+// whatever it reaches, provided that's the sampled point. With `mis` the light is weighted against
+// the chance of the material scattering towards it instead.
+fn direct_lighting(world: &dyn Hitable, lights: &Lights, ray: &Ray, hit: &Hit, mis: bool, sampler: &mut dyn Sampler, ray_count: &mut u64) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let (direction, distance, pdf) = lights.sample(&hit.location, sampler)?;
+    let reflectance = hit.material.evaluate(ray, hit, &direction);
+    if reflectance == vec3(0., 0., 0.) {
+        return None;
+    }
+
     *ray_count += 1;
-    let hit = hit(shapes, ray, &Interval { min: 0.001, max: f32::MAX });
-    match hit {
-        None => {
-            // let t = 0.5 * (ray.direction.y + 1.0);
-            // ((1.0 - t) * vec3(1., 1., 1.)) + (t * vec3(0.5, 0.7, 1.0))
-            vec3(0., 0., 0.)
+    let shadow_ray = Ray { origin: hit.location, direction, wavelength: None };
+    let emitted = match world.hit(&shadow_ray, &Interval { min: 0.001, max: (distance * (1. + SHADOW_EPSILON)).min(f32::MAX) }) {
+        Some(ref light_hit) if light_hit.distance > distance * (1. - SHADOW_EPSILON) => {
+            light_hit.material.emit(light_hit.uv.x, light_hit.uv.y, &light_hit.location)
         },
-        Some(hit) => {
-            // :TODO: UVs
-            let emitted = hit.material.emit(0., 0., &hit.location);
-            if depth < 50 {
-                let scatter_result = hit.material.scatter(ray, &hit);
-                match scatter_result {
-                    None => emitted,//vec3(0., 0., 0.),
-                    Some(scatter_result) => emitted + scatter_result.attenuation.mul_element_wise(trace(shapes, &scatter_result.ray, depth + 1, ray_count))
+        None if distance == f32::INFINITY => lights.environment?.radiance(&direction),
+        _ => return None,
+    };
+    let weight = if mis { power_heuristic(pdf, hit.material.pdf(ray, hit, &direction)) } else { 1. };
+    Some((emitted * (weight / pdf), reflectance))
+}
+
+// Path tracing with next-event estimation: every non-specular bounce also samples a light
+// directly. Emission then only counts when reached by a camera ray or specular bounce (or from
+// emitters that aren't lights), otherwise it would be added twice. The same goes for the
+// environment.
+pub fn trace_with_light_sampling(world: &Bvh, lights: &Lights, ray: &Ray, colours: PathColours, limits: &PathLimits, sampler: &mut dyn Sampler, ray_count: &mut u64) -> Vector3<f32> {
+    let mut radiance = Spectrum::zero();
+    let mut path = Path::new(colours);
+    let mut ray = *ray;
+    let mut count_emitted = true;
+    for depth in 0.. {
+        *ray_count += 1;
+        let (index, hit) = match world.hit_with_index(&ray, &Interval { min: 0.001, max: f32::MAX }) {
+            None => {
+                if let (Some(environment), true) = (lights.environment, count_emitted) {
+                    radiance += path.weight(environment.radiance(&ray.direction.normalize()));
                 }
-            } else {
-                emitted//vec3(0., 0., 0.)
+                break;
+            },
+            Some(indexed_hit) => indexed_hit,
+        };
+
+        let sampled_directly = !count_emitted && hit.material.is_emitting() && lights.pdf(index, &ray.origin, &ray.direction.normalize()) > 0.;
+        if !sampled_directly {
+            radiance += path.weight(hit.material.emit(hit.uv.x, hit.uv.y, &hit.location));
+        }
+
+        // Lights are sampled even if the material absorbs the ray, it may still reflect them
+        sampler.set_dimension(samplers::bounce_dimension(depth, samplers::SCATTER_DIMENSIONS));
+        let scattered = hit.material.scatter(&ray, &hit, sampler);
+        if !scattered.as_ref().is_some_and(|scattered| scattered.specular) {
+            sampler.set_dimension(samplers::bounce_dimension(depth, samplers::LIGHT_DIMENSIONS));
+            if let Some((light, reflectance)) = direct_lighting(world, lights, &ray, &hit, false, sampler, ray_count) {
+                radiance += path.weight(light).mul_element_wise(colours.from_rgb(reflectance));
             }
         }
+        match scattered {
+            Some(scattered) if path.continues(limits, &ray, &hit, &scattered, depth, sampler) => {
+                count_emitted = scattered.specular;
+                ray = scattered.ray;
+            },
+            _ => break,
+        }
     }
-}
\ No newline at end of file
+    colours.to_rgb(radiance)
+}
+
+// Path tracing combining light and material sampling with multiple importance sampling, so each
+// is favoured where it does best: lights for diffuse surfaces and large lights, the material for
+// glossy reflections and small, bright lights.
+pub fn trace_with_mis(world: &Bvh, lights: &Lights, ray: &Ray, colours: PathColours, limits: &PathLimits, sampler: &mut dyn Sampler, ray_count: &mut u64) -> Vector3<f32> {
+    let mut radiance = Spectrum::zero();
+    let mut path = Path::new(colours);
+    let mut ray = *ray;
+    // The pdf of the material sampling that produced `ray`, or None for camera rays and specular
+    // bounces
+    let mut scattering_pdf: Option<f32> = None;
+    for depth in 0.. {
+        *ray_count += 1;
+        let (index, hit) = match world.hit_with_index(&ray, &Interval { min: 0.001, max: f32::MAX }) {
+            None => {
+                if let Some(environment) = lights.environment {
+                    let direction = ray.direction.normalize();
+                    let mut emitted = environment.radiance(&direction);
+                    if let Some(scattering_pdf) = scattering_pdf {
+                        emitted *= power_heuristic(scattering_pdf, lights.environment_pdf(&direction));
+                    }
+                    radiance += path.weight(emitted);
+                }
+                break;
+            },
+            Some(indexed_hit) => indexed_hit,
+        };
+
+        let mut emitted = hit.material.emit(hit.uv.x, hit.uv.y, &hit.location);
+        if let Some(scattering_pdf) = scattering_pdf {
+            if hit.material.is_emitting() {
+                emitted *= power_heuristic(scattering_pdf, lights.pdf(index, &ray.origin, &ray.direction.normalize()));
+            }
+        }
+        radiance += path.weight(emitted);
+
+        sampler.set_dimension(samplers::bounce_dimension(depth, samplers::SCATTER_DIMENSIONS));
+        let scattered = hit.material.scatter(&ray, &hit, sampler);
+        if !scattered.as_ref().is_some_and(|scattered| scattered.specular) {
+            sampler.set_dimension(samplers::bounce_dimension(depth, samplers::LIGHT_DIMENSIONS));
+            if let Some((light, reflectance)) = direct_lighting(world, lights, &ray, &hit, true, sampler, ray_count) {
+                radiance += path.weight(light).mul_element_wise(colours.from_rgb(reflectance));
+            }
+        }
+        match scattered {
+            Some(scattered) if path.continues(limits, &ray, &hit, &scattered, depth, sampler) => {
+                scattering_pdf = if scattered.specular { None } else { Some(scattered.pdf) };
+                ray = scattered.ray;
+            },
+            _ => break,
+        }
+    }
+    colours.to_rgb(radiance)
+}
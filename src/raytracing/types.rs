@@ -1,10 +1,17 @@
 use cgmath::*;
+use raytracing::samplers::Sampler;
+use raytracing::util::{maths, random};
+use std::f32;
+use std::sync::Arc;
 
 pub trait ScatteringAndEmitting : Scattering + Emitting {}
 
+#[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub origin: Point3<f32>,
-    pub direction: Vector3<f32>
+    pub direction: Vector3<f32>,
+    // The hero wavelength (in nm) of the path in spectral mode, for materials that depend on it
+    pub wavelength: Option<f32>,
 }
 
 pub struct Interval {
@@ -12,34 +19,234 @@ pub struct Interval {
     pub max: f32
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn from_points(points: &[Point3<f32>]) -> Aabb {
+        let mut min = points[0];
+        let mut max = points[0];
+        for p in &points[1..] {
+            min = Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        Aabb { min, max }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_points(&[self.min, self.max, other.min, other.max])
+    }
+
+    pub fn include(&self, p: Point3<f32>) -> Aabb {
+        Aabb::from_points(&[self.min, self.max, p])
+    }
+
+    pub fn centroid(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn extent(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.extent();
+        2. * (d.x * d.y + d.x * d.z + d.y * d.z)
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z), Point3::new(b.x, a.y, a.z), Point3::new(a.x, b.y, a.z), Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z), Point3::new(b.x, a.y, b.z), Point3::new(a.x, b.y, b.z), Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    // Slab test, taking the reciprocal of the ray direction so it can be shared across many boxes.
+    pub fn hit(&self, origin: &Point3<f32>, inv_direction: &Vector3<f32>, interval: &Interval) -> bool {
+        let mut tmin = interval.min;
+        let mut tmax = interval.max;
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inv_direction[axis];
+            let (t0, t1) = if inv_direction[axis] < 0. { (t1, t0) } else { (t0, t1) };
+            tmin = tmin.max(t0);
+            tmax = tmax.min(t1);
+            if tmax < tmin {
+                return false;
+            }
+        }
+        true
+    }
+}
+
 pub struct Hit<'a> {
     pub distance: f32,
     pub location: Point3<f32>,
     pub normal: Vector3<f32>,
-    pub material: &'a(ScatteringAndEmitting + 'a), // :TODO: Better undestand lifetime use here
-    // pub uv: Point2<f32>,
+    // The true surface normal, which `normal` differs from when interpolated across a mesh
+    pub geometric_normal: Vector3<f32>,
+    pub material: &'a(dyn ScatteringAndEmitting + 'a), // :TODO: Better undestand lifetime use here
+    pub uv: Point2<f32>,
 }
 
 pub struct ScatteredRay {
     pub ray: Ray,
+    // The BSDF times the cosine term over the pdf
     pub attenuation: Vector3<f32>,
+    // Solid angle pdf of picking the scattered direction, zero for specular bounces
+    pub pdf: f32,
+    // Specular (mirror or glass) bounces can't be lit by sampling lights directly
+    pub specular: bool,
+    // The direction was picked for the ray's wavelength, so only that one carries on along the path
+    pub dispersed: bool,
+}
+
+// A point sampled on the surface of a shape, with the pdf taken with respect to solid angle as seen
+// from the point being lit.
+pub struct ShapeSample {
+    pub location: Point3<f32>,
+    pub normal: Vector3<f32>,
+    pub pdf: f32,
+}
+
+impl ShapeSample {
+    // Converts a pdf with respect to surface area, as used when sampling a point on a shape.
+    pub fn new(origin: &Point3<f32>, location: Point3<f32>, normal: Vector3<f32>, area_pdf: f32) -> ShapeSample {
+        let to_location = location - origin;
+        let distance = to_location.magnitude();
+        let cosine = dot(to_location / distance, normal);
+        ShapeSample { location, normal, pdf: maths::area_to_solid_angle_pdf(area_pdf, distance, cosine) }
+    }
 }
 
 pub trait Hitable {
-    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit>;
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>>;
+
+    // Shapes with no finite extent (e.g. an infinite plane) return None.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // Whether the shape emits light and can be sampled, i.e. belongs in the scene's light list.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    fn area(&self) -> f32 {
+        0.
+    }
+
+    // Samples a point on the shape to light `origin` with. Unless overridden, `pdf` assumes points
+    // are sampled uniformly by area.
+    fn sample(&self, _origin: &Point3<f32>, _sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        None
+    }
+
+    // Solid angle pdf of `sample` picking the point hit by the ray from `origin` along the unit
+    // vector `direction`.
+    fn pdf(&self, origin: &Point3<f32>, direction: &Vector3<f32>) -> f32 {
+        let ray = Ray { origin: *origin, direction: *direction, wavelength: None };
+        match self.hit(&ray, &Interval { min: 0.001, max: f32::MAX }) {
+            Some(hit) => maths::area_to_solid_angle_pdf(1. / self.area(), hit.distance, dot(*direction, hit.geometric_normal)),
+            None => 0.,
+        }
+    }
 }
 
-pub type BoxedHitable = Box<Hitable + Sync>;
+// Lets a single shape (e.g. a large mesh) be shared between several instances.
+impl<H: Hitable + ?Sized> Hitable for Arc<H> {
+    fn hit(&self, ray: &Ray, interval: &Interval) -> Option<Hit<'_>> {
+        (**self).hit(ray, interval)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+
+    fn area(&self) -> f32 {
+        (**self).area()
+    }
+
+    fn sample(&self, origin: &Point3<f32>, sampler: &mut dyn Sampler) -> Option<ShapeSample> {
+        (**self).sample(origin, sampler)
+    }
+
+    fn pdf(&self, origin: &Point3<f32>, direction: &Vector3<f32>) -> f32 {
+        (**self).pdf(origin, direction)
+    }
+}
+
+pub type BoxedHitable = Box<dyn Hitable + Send + Sync>;
 pub type HitableCollection = Vec<BoxedHitable>;
 
 pub trait Scattering {
-    fn scatter(&self, ray: &Ray, hit: &Hit) -> Option<ScatteredRay>;
+    fn scatter(&self, ray: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<ScatteredRay>;
+
+    // The BSDF times the cosine term for light arriving from the unit vector `direction` and leaving
+    // back along `ray`. Zero for specular materials, which can only be sampled.
+    fn evaluate(&self, _ray: &Ray, _hit: &Hit, _direction: &Vector3<f32>) -> Vector3<f32> {
+        vec3(0., 0., 0.)
+    }
+
+    // Solid angle pdf of `scatter` picking `direction`.
+    fn pdf(&self, _ray: &Ray, _hit: &Hit, _direction: &Vector3<f32>) -> f32 {
+        0.
+    }
+
+    // Reflectance for the albedo AOV. White unless overridden, as for glass, which passes light on
+    // unchanged, and lights.
+    fn albedo(&self, _hit: &Hit) -> Vector3<f32> {
+        vec3(1., 1., 1.)
+    }
 }
 
 pub trait Emitting {
+    fn is_emitting(&self) -> bool {
+        false
+    }
+
     fn emit(&self, _u: f32, _v: f32, _p: &Point3<f32>) -> Vector3<f32> {
         vec3(0., 0., 0.)
     }
 }
 
-pub type Material = Box<ScatteringAndEmitting+Sync>;
\ No newline at end of file
+pub trait Texture {
+    fn value(&self, u: f32, v: f32, p: &Point3<f32>) -> Vector3<f32>;
+}
+
+pub type BoxedTexture = Box<dyn Texture + Send + Sync>;
+
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f32, v: f32, p: &Point3<f32>) -> Vector3<f32> {
+        (**self).value(u, v, p)
+    }
+}
+
+pub type Material = Box<dyn ScatteringAndEmitting + Send + Sync>;
+
+// Light arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment {
+    // Radiance arriving from the unit vector `direction`.
+    fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32>;
+
+    // Samples a direction to light from, with its solid angle pdf. Uniform over the sphere unless
+    // overridden.
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vector3<f32>, f32)> {
+        Some((random::random_unit_vector(sampler), 1. / (4. * f32::consts::PI)))
+    }
+
+    // Solid angle pdf of `sample` picking `direction`.
+    fn pdf(&self, _direction: &Vector3<f32>) -> f32 {
+        1. / (4. * f32::consts::PI)
+    }
+}
+
+pub type BoxedEnvironment = Box<dyn Environment + Send + Sync>;
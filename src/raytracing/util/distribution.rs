@@ -0,0 +1,111 @@
+use cgmath::*;
+
+// A piecewise constant function over [0, 1), sampled in proportion to its values by inverting its
+// CDF.
+pub struct Distribution1D {
+    function: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(function: Vec<f32>) -> Distribution1D {
+        let n = function.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.);
+        for (i, value) in function.iter().enumerate() {
+            let previous = cdf[i];
+            cdf.push(previous + value.abs() / n as f32);
+        }
+        let integral = cdf[n];
+        // A function of zeros is sampled uniformly, though with a pdf of zero
+        if integral > 0. {
+            for c in &mut cdf {
+                *c /= integral;
+            }
+        } else {
+            cdf = (0..=n).map(|i| i as f32 / n as f32).collect();
+        }
+        Distribution1D { function, cdf, integral }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    fn len(&self) -> usize {
+        self.function.len()
+    }
+
+    // Maps `u` in [0, 1) to a point in [0, 1), returning it with its pdf and the segment it's in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.function.len();
+        let segment = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[segment + 1] - self.cdf[segment];
+        let offset = if width > 0. { ((u - self.cdf[segment]) / width).min(1.) } else { 0. };
+        ((segment as f32 + offset) / n as f32, self.segment_pdf(segment), segment)
+    }
+
+    fn segment_pdf(&self, segment: usize) -> f32 {
+        if self.integral > 0. { self.function[segment].abs() / self.integral } else { 0. }
+    }
+}
+
+// A piecewise constant function over [0, 1)², given row by row, sampled by picking a row from the
+// marginal distribution and then a point along it.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(function: &[f32], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = function.chunks(width).take(height).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    pub fn sample(&self, u: Point2<f32>) -> (Point2<f32>, f32) {
+        let (y, row_pdf, row) = self.marginal.sample(u.y);
+        let (x, pdf, _) = self.rows[row].sample(u.x);
+        (Point2::new(x, y), row_pdf * pdf)
+    }
+
+    pub fn pdf(&self, p: Point2<f32>) -> f32 {
+        let row = ((p.y * self.marginal.len() as f32) as usize).min(self.marginal.len() - 1);
+        let column = ((p.x * self.rows[row].len() as f32) as usize).min(self.rows[row].len() - 1);
+        if self.marginal.integral() > 0. { self.rows[row].function[column].abs() / self.marginal.integral() } else { 0. }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampled_pdfs_match_the_function() {
+        let distribution = Distribution1D::new(vec![1., 0., 3., 0.5, 0., 2.]);
+        let n = distribution.len() as f32;
+        let total: f32 = (0..distribution.len()).map(|segment| distribution.segment_pdf(segment) / n).sum();
+        assert!((total - 1.).abs() < 1e-6, "pdf integrates to {}", total);
+        for i in 0..1000 {
+            let (x, pdf, segment) = distribution.sample(i as f32 / 1000.);
+            assert!((0. ..1.).contains(&x));
+            assert_eq!(segment, (x * n) as usize, "{} isn't in segment {}", x, segment);
+            assert!(pdf > 0., "sampled {} where the function is zero", x);
+            assert_eq!(pdf, distribution.segment_pdf(segment));
+        }
+
+        let (width, height) = (5, 4);
+        let function: Vec<f32> = (0..width * height).map(|i| ((i * 7) % 5 + i / width * 2) as f32).collect();
+        let distribution = Distribution2D::new(&function, width, height);
+        for i in 0..50 {
+            for j in 0..50 {
+                let (p, pdf) = distribution.sample(Point2::new(i as f32 / 50., j as f32 / 50.));
+                assert!((0. ..1.).contains(&p.x) && (0. ..1.).contains(&p.y));
+                assert!(pdf > 0., "sampled {:?} where the function is zero", p);
+                assert!((pdf - distribution.pdf(p)).abs() <= 1e-5 * pdf, "sample gave a pdf of {} at {:?}, pdf() {}", pdf, p, distribution.pdf(p));
+            }
+        }
+    }
+}
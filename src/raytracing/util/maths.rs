@@ -20,4 +20,28 @@ pub fn schlick(cosine: f32, refractive_index: f32) -> f32 {
     let r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
     let r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
-}
\ No newline at end of file
+}
+
+// Converts a pdf with respect to surface area into one with respect to solid angle, for a point
+// `distance` away whose surface is at `cosine` to the direction towards it.
+pub fn area_to_solid_angle_pdf(area_pdf: f32, distance: f32, cosine: f32) -> f32 {
+    let cosine = cosine.abs();
+    if cosine > 0. && distance > 0. {
+        area_pdf * distance * distance / cosine
+    } else {
+        0.
+    }
+}
+
+// Two unit vectors perpendicular to the unit vector `w` and each other.
+pub fn orthonormal_basis(w: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let a = if w.x.abs() > 0.9 { vec3(0., 1., 0.) } else { vec3(1., 0., 0.) };
+    let v = w.cross(a).normalize();
+    let u = w.cross(v);
+    (u, v)
+}
+
+// Rec. 709 luminance of linear RGB
+pub fn luminance(c: Vector3<f32>) -> f32 {
+    dot(c, vec3(0.2126, 0.7152, 0.0722))
+}
@@ -1,2 +1,3 @@
+pub mod distribution;
 pub mod maths;
-pub mod random;
\ No newline at end of file
+pub mod random;
@@ -1,30 +1,81 @@
 use cgmath::*;
-use rand::{random};
+use raytracing::samplers::Sampler;
 use std::f32;
 
-pub fn random_in_unit_sphere() -> Vector3<f32> {
-    // :TODD: Check that the initial random vector has values in the range 0..1
-    loop {
-        let p = (2.0 * random::<Vector3<f32>>()) - vec3(1., 1., 1.);
-        if p.magnitude2() < 1. {
-            return p;
-        }
+// PCG32 (XSH RR variant), a small generator whose whole state is two integers, so a fresh one can
+// be cheaply created for every sample.
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+const PCG_MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+// SplitMix64's finaliser, spreading similar inputs (neighbouring pixels, consecutive samples) over
+// unrelated seeds.
+pub fn mix_bits(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Rng {
+        let mut rng = Rng { state: 0, increment: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    // The random numbers for one sample of one pixel depend only on these, not on which thread
+    // renders the pixel or what it rendered before, so a render can be repeated exactly and any
+    // single sample replayed.
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Rng {
+        Rng::new(mix_bits(mix_bits(seed ^ mix_bits(pixel)) ^ sample), pixel)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // Uniform in [0, 1), using the top 24 bits so the result is never rounded up to 1.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1. / (1u32 << 24) as f32)
     }
 }
 
-pub fn random_in_unit_disk() -> Vector3<f32> {
-    loop {
-        let p = 2.0 * vec3(random::<f32>(), random::<f32>(), 0.) - vec3(1., 1., 0.);
-        if dot(p, p) < 1. {
-            return p;
-        }
+// A direction and a distance, with the cube root spreading the points evenly by volume.
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vector3<f32> {
+    random_unit_vector(sampler) * sampler.get_1d().cbrt()
+}
+
+// Shirley and Chiu's concentric mapping of the square onto the disk. Unlike rejection sampling it
+// always takes two dimensions, and keeps their stratification.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vector3<f32> {
+    let u = sampler.get_2d();
+    let (a, b) = (2. * u.x - 1., 2. * u.y - 1.);
+    if a == 0. && b == 0. {
+        return Vector3::zero();
     }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, f32::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (b, f32::consts::FRAC_PI_2 - f32::consts::FRAC_PI_4 * (a / b))
+    };
+    vec3(r * theta.cos(), r * theta.sin(), 0.)
 }
 
-pub fn random_unit_vector() -> Vector3<f32> {
-    let z = (2.0 * random::<f32>()) - 1.0;
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vector3<f32> {
+    let u = sampler.get_2d();
+    let z = (2.0 * u.x) - 1.0;
     let tmp = (1.0 - (z * z)).sqrt();
-    let azimuth = random::<f32>() * 2.0 * f32::consts::PI;
+    let azimuth = u.y * 2.0 * f32::consts::PI;
     let x = azimuth.cos() * tmp;
     let y = azimuth.sin() * tmp;
     vec3(x, y, z)
@@ -33,4 +84,4 @@ pub fn random_unit_vector() -> Vector3<f32> {
     // let p = (2. * random::<Vector3<f32>>()) - vec3(1., 1., 1.);
     // let tmp = 1.0 / p.mul_element_wise(p).sum().sqrt();
     // p * tmp
-}
\ No newline at end of file
+}